@@ -1,10 +1,18 @@
 use candid::{CandidType, Deserialize, Principal};
-use ic_cdk::api::time;
-use ic_cdk::{caller, trap};
+use ic_cdk::trap;
+// The system API panics off-wasm, so unit tests read the clock and caller from `mock`.
+#[cfg(not(test))]
+use ic_cdk::{api::time, caller};
+#[cfg(test)]
+use mock::{caller, time};
 use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
 use std::cell::RefCell;
 use std::collections::{BTreeMap, BTreeSet};
 
+const CANISTER_VERSION: &str = "admin-canister-v1";
+const CANDID_SCHEMA: &str = include_str!("../Admin.did");
+const SCHEMA_VERSION: u32 = 1; // layout of the saved `State`; bump when a change needs migrating
+
 /// ====== Domain Types ======
 
 #[derive(Clone, Debug, CandidType, Deserialize, PartialEq, Eq)]
@@ -54,16 +62,78 @@ pub enum AdminError {
     InvalidInput(String),
 }
 
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AdminLogEntry {
+    pub at_ns: u64,
+    pub admin: Principal,
+    pub action: String, // e.g. "approve_idea", "reopen_idea"
+    pub idea_id: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AdminGrant {
+    pub principal: Principal,
+    pub granted_at_ns: u64,
+    pub granted_by: Principal,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct VersionInfo {
+    pub version: String,
+    pub candid_schema_hash: String, // FNV-1a of Admin.did
+    pub build_time: Option<String>,
+}
+
 type Result<T> = std::result::Result<T, AdminError>;
 
 /// ====== State ======
 
 #[derive(Default, CandidType, Deserialize , Clone)]
 struct State {
+    users: BTreeMap<Principal, RegisteredUser>,
+    ideas: BTreeMap<u64, Idea>,
+    next_idea_id: u64,
+    admins: BTreeMap<Principal, AdminGrant>,
+    // Fields added after the first release are optional so state saved by older versions still decodes.
+    admin_log: Option<Vec<AdminLogEntry>>,
+    schema_version: Option<u32>, // `SCHEMA_VERSION` of the code that saved it; None before versioning
+}
+
+/// State as saved before admins carried grant metadata; only used to migrate in `post_upgrade`.
+#[derive(CandidType, Deserialize)]
+struct LegacyState {
     users: BTreeMap<Principal, RegisteredUser>,
     ideas: BTreeMap<u64, Idea>,
     next_idea_id: u64,
     admins: BTreeSet<Principal>,
+    admin_log: Option<Vec<AdminLogEntry>>,
+}
+
+impl From<LegacyState> for State {
+    fn from(old: LegacyState) -> Self {
+        let admins = old
+            .admins
+            .into_iter()
+            .map(|p| {
+                (
+                    p,
+                    AdminGrant {
+                        principal: p,
+                        granted_at_ns: 0,
+                        granted_by: Principal::anonymous(),
+                    },
+                )
+            })
+            .collect();
+        State {
+            users: old.users,
+            ideas: old.ideas,
+            next_idea_id: old.next_idea_id,
+            admins,
+            admin_log: old.admin_log,
+            schema_version: None,
+        }
+    }
 }
 
 thread_local! {
@@ -72,7 +142,16 @@ thread_local! {
 
 /// Small helpers
 fn is_admin(p: Principal) -> bool {
-    STATE.with(|s| s.borrow().admins.contains(&p))
+    STATE.with(|s| s.borrow().admins.contains_key(&p))
+}
+
+/// Record `p` as an admin; an existing grant keeps its original timestamp and grantor.
+fn grant_admin(st: &mut State, p: Principal, now: u64) {
+    st.admins.entry(p).or_insert(AdminGrant {
+        principal: p,
+        granted_at_ns: now,
+        granted_by: caller(),
+    });
 }
 
 fn ensure_admin() -> Result<()> {
@@ -83,6 +162,15 @@ fn ensure_admin() -> Result<()> {
     }
 }
 
+fn log_action(st: &mut State, action: &str, idea_id: u64) {
+    st.admin_log.get_or_insert_with(Vec::new).push(AdminLogEntry {
+        at_ns: time(),
+        admin: caller(),
+        action: action.to_string(),
+        idea_id,
+    });
+}
+
 /// ====== Lifecycle ======
 
 #[init]
@@ -91,7 +179,7 @@ fn init() {
     let me = caller();
     STATE.with(|s| {
         let mut st = s.borrow_mut();
-        st.admins.insert(me);
+        grant_admin(&mut st, me, time());
         // optionally bootstrap a user record for the installer
         st.users.entry(me).or_insert(RegisteredUser {
             principal: me,
@@ -103,15 +191,49 @@ fn init() {
     });
 }
 
+/// Refuse data written by a newer schema; an older wasm would misread or drop what it added.
+fn check_schema_version(stored: u32) -> std::result::Result<(), String> {
+    if stored > SCHEMA_VERSION {
+        return Err(format!("stable data uses schema v{}, newer than v{}; refusing to downgrade", stored, SCHEMA_VERSION));
+    }
+    Ok(())
+}
+
 #[pre_upgrade]
 fn pre_upgrade() {
-    let state = STATE.with(|s| s.borrow().clone());
+    let mut state = STATE.with(|s| s.borrow().clone());
+    state.schema_version = Some(SCHEMA_VERSION);
     ic_cdk::storage::stable_save((state,)).expect("stable_save failed");
 }
 
+/// Decode what `pre_upgrade` saved, as `ic_cdk::storage::stable_restore` would (bytes past the
+/// saved state are ignored). Older versions stored admins as a plain set; those are migrated
+/// with unknown grant metadata.
+fn decode_saved_state(bytes: &[u8]) -> std::result::Result<State, String> {
+    fn decode<T: for<'de> candid::utils::ArgumentDecoder<'de>>(bytes: &[u8]) -> std::result::Result<T, String> {
+        let mut de = candid::de::IDLDeserialize::new(bytes).map_err(|e| format!("{:?}", e))?;
+        candid::utils::ArgumentDecoder::decode(&mut de).map_err(|e| format!("{:?}", e))
+    }
+    match decode::<(State,)>(bytes) {
+        Ok((state,)) => Ok(state),
+        Err(e) => decode::<(LegacyState,)>(bytes).map(|(old,)| old.into()).map_err(|_| e),
+    }
+}
+
 #[post_upgrade]
 fn post_upgrade() {
-    let (state,): (State,) = ic_cdk::storage::stable_restore().unwrap_or_default();
+    // nothing was saved (upgrading from a build without `pre_upgrade`)
+    if ic_cdk::api::stable::stable_size() == 0 {
+        return;
+    }
+    let state = match decode_saved_state(&ic_cdk::api::stable::stable_bytes()) {
+        Ok(state) => state,
+        // trapping rolls the upgrade back; starting empty would wipe every user and idea
+        Err(e) => trap(&format!("could not decode saved state: {}", e)),
+    };
+    if let Err(e) = check_schema_version(state.schema_version.unwrap_or(0)) {
+        trap(&e);
+    }
     STATE.with(|s| *s.borrow_mut() = state);
 }
 
@@ -123,7 +245,7 @@ fn register_user(name: String, email: String) -> RegisteredUser {
     let now = time();
     STATE.with(|s| {
         let mut st = s.borrow_mut();
-        let is_admin = st.admins.contains(&me);
+        let is_admin = st.admins.contains_key(&me);
         let entry = st.users.entry(me).or_insert(RegisteredUser {
             principal: me,
             name: name.clone(),
@@ -143,9 +265,9 @@ fn add_admin(p: Principal) -> Result<()> {
     ensure_admin()?;
     STATE.with(|s| {
         let mut st = s.borrow_mut();
-        st.admins.insert(p);
         // ensure user exists and has role Admin
         let now = time();
+        grant_admin(&mut st, p, now);
         st.users
             .entry(p)
             .and_modify(|u| u.role = Role::Admin)
@@ -167,10 +289,10 @@ fn remove_admin(p: Principal) -> Result<()> {
     // Prevent removing the last admin or self-locking
     STATE.with(|s| {
         let mut st = s.borrow_mut();
-        if !st.admins.contains(&p) {
+        if !st.admins.contains_key(&p) {
             return;
         }
-        if st.admins.len() == 1 && st.admins.contains(&p) {
+        if st.admins.len() == 1 && st.admins.contains_key(&p) {
             trap("Cannot remove the last admin");
         }
         // avoid removing yourself if you'd become non-admin and there's no other admin left
@@ -193,22 +315,75 @@ fn set_role(p: Principal, role: Role) -> Result<()> {
         let user = st.users.get_mut(&p).ok_or(AdminError::UserNotFound)?;
         user.role = role.clone();
         match role {
-            Role::Admin => { st.admins.insert(p); }
+            Role::Admin => grant_admin(&mut st, p, time()),
             Role::User => { st.admins.remove(&p); }
         }
         Ok(())
     })
 }
 
+/// Apply several role assignments in order (admin only), each independently: a bad entry gets
+/// its own error without aborting the rest. Granting Admin creates the user record if needed,
+/// like `add_admin`; demoting requires a known user and can never remove the last admin.
+#[update]
+fn bulk_set_role(assignments: Vec<(Principal, Role)>) -> Vec<Result<()>> {
+    if let Err(e) = ensure_admin() {
+        return assignments.iter().map(|_| Err(e.clone())).collect();
+    }
+    let now = time();
+    STATE.with(|s| {
+        let mut st = s.borrow_mut();
+        assignments
+            .into_iter()
+            .map(|(p, role)| match role {
+                Role::Admin => {
+                    grant_admin(&mut st, p, now);
+                    st.users
+                        .entry(p)
+                        .and_modify(|u| u.role = Role::Admin)
+                        .or_insert(RegisteredUser {
+                            principal: p,
+                            name: "Admin".into(),
+                            email: "".into(),
+                            role: Role::Admin,
+                            registered_at_ns: now,
+                        });
+                    Ok(())
+                }
+                Role::User => {
+                    if !st.users.contains_key(&p) {
+                        return Err(AdminError::UserNotFound);
+                    }
+                    if st.admins.contains_key(&p) && st.admins.len() == 1 {
+                        return Err(AdminError::InvalidInput("cannot remove the last admin".into()));
+                    }
+                    st.admins.remove(&p);
+                    if let Some(u) = st.users.get_mut(&p) {
+                        u.role = Role::User;
+                    }
+                    Ok(())
+                }
+            })
+            .collect()
+    })
+}
+
 #[query]
 fn get_users() -> Vec<RegisteredUser> {
     STATE.with(|s| s.borrow().users.values().cloned().collect())
 }
 
+/// Every current admin with when and by whom they were granted. Admins migrated from older state
+/// report a zero timestamp and the anonymous principal as grantor.
+#[query]
+fn get_admins() -> Vec<AdminGrant> {
+    STATE.with(|s| s.borrow().admins.values().cloned().collect())
+}
+
 #[query]
 fn get_my_role() -> Role {
     STATE.with(|s| {
-        if s.borrow().admins.contains(&caller()) {
+        if s.borrow().admins.contains_key(&caller()) {
             Role::Admin
         } else {
             s.borrow()
@@ -255,9 +430,10 @@ fn approve_idea(id: u64) -> Result<ApproveRejectResult> {
         let mut st = s.borrow_mut();
         let idea = st.ideas.get_mut(&id).ok_or(AdminError::IdeaNotFound)?;
         idea.status = IdeaStatus::Approved;
+        log_action(&mut st, "approve_idea", id);
         Ok(ApproveRejectResult {
             id,
-            status: idea.status.clone(),
+            status: IdeaStatus::Approved,
         })
     })
 }
@@ -269,13 +445,39 @@ fn reject_idea(id: u64) -> Result<ApproveRejectResult> {
         let mut st = s.borrow_mut();
         let idea = st.ideas.get_mut(&id).ok_or(AdminError::IdeaNotFound)?;
         idea.status = IdeaStatus::Rejected;
+        log_action(&mut st, "reject_idea", id);
+        Ok(ApproveRejectResult {
+            id,
+            status: IdeaStatus::Rejected,
+        })
+    })
+}
+
+/// Move an Approved or Rejected idea back to Pending for reconsideration.
+#[update]
+fn reopen_idea(id: u64) -> Result<ApproveRejectResult> {
+    ensure_admin()?;
+    STATE.with(|s| {
+        let mut st = s.borrow_mut();
+        let idea = st.ideas.get_mut(&id).ok_or(AdminError::IdeaNotFound)?;
+        if idea.status == IdeaStatus::Pending {
+            return Err(AdminError::InvalidInput("Idea is already pending".into()));
+        }
+        idea.status = IdeaStatus::Pending;
+        log_action(&mut st, "reopen_idea", id);
         Ok(ApproveRejectResult {
             id,
-            status: idea.status.clone(),
+            status: IdeaStatus::Pending,
         })
     })
 }
 
+#[query]
+fn get_admin_log() -> Result<Vec<AdminLogEntry>> {
+    ensure_admin()?;
+    STATE.with(|s| Ok(s.borrow().admin_log.clone().unwrap_or_default()))
+}
+
 #[query]
 fn get_ideas() -> Vec<Idea> {
     STATE.with(|s| s.borrow().ideas.values().cloned().collect())
@@ -286,5 +488,131 @@ fn get_idea(id: u64) -> Option<Idea> {
     STATE.with(|s| s.borrow().ideas.get(&id).cloned())
 }
 
+#[query]
+fn get_version() -> VersionInfo {
+    let hash = CANDID_SCHEMA.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    VersionInfo {
+        version: CANISTER_VERSION.to_string(),
+        candid_schema_hash: format!("{:016x}", hash),
+        build_time: option_env!("BUILD_TIME").map(str::to_string),
+    }
+}
+
 ic_cdk::export_candid!();
 
+/// Stand-ins for the system API in unit tests.
+#[cfg(test)]
+mod mock {
+    use candid::Principal;
+    use std::cell::Cell;
+
+    thread_local! {
+        pub static NOW_NS: Cell<u64> = const { Cell::new(0) };
+        pub static CALLER: Cell<Principal> = const { Cell::new(Principal::anonymous()) };
+    }
+
+    pub fn time() -> u64 {
+        NOW_NS.with(|n| n.get())
+    }
+
+    pub fn caller() -> Principal {
+        CALLER.with(|c| c.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(n: u8) -> Principal {
+        Principal::from_slice(&[n; 29])
+    }
+
+    fn as_caller(p: Principal) {
+        mock::CALLER.with(|c| c.set(p));
+    }
+
+    /// Install as `admin`, then submit one idea from another principal.
+    fn setup_with_idea() -> u64 {
+        as_caller(user(1));
+        init();
+        as_caller(user(2));
+        let id = submit_idea("Solar kiosk".into(), "Prepaid solar charging for markets".into()).unwrap().id;
+        as_caller(user(1));
+        id
+    }
+
+    #[test]
+    fn reopen_moves_approved_and_rejected_ideas_back_to_pending() {
+        let id = setup_with_idea();
+        assert!(matches!(reopen_idea(id), Err(AdminError::InvalidInput(_))));
+
+        approve_idea(id).unwrap();
+        assert_eq!(reopen_idea(id).unwrap().status, IdeaStatus::Pending);
+        reject_idea(id).unwrap();
+        assert_eq!(reopen_idea(id).unwrap().status, IdeaStatus::Pending);
+        assert_eq!(get_idea(id).unwrap().status, IdeaStatus::Pending);
+
+        let log = get_admin_log().unwrap();
+        let reopens = log.iter().filter(|e| e.action == "reopen_idea" && e.idea_id == id).count();
+        assert_eq!(reopens, 2);
+
+        as_caller(user(2));
+        approve_idea(id).unwrap_err();
+        assert!(matches!(reopen_idea(id), Err(AdminError::NotAuthorized)));
+    }
+
+    #[test]
+    fn bulk_set_role_reports_each_entry_and_keeps_the_last_admin() {
+        as_caller(user(1));
+        init();
+        add_admin(user(3)).unwrap();
+
+        // demoting every admin: all but the last demotion go through
+        let results = bulk_set_role(vec![(user(3), Role::User), (user(1), Role::User), (user(4), Role::User)]);
+        assert!(matches!(
+            results.as_slice(),
+            [Ok(()), Err(AdminError::InvalidInput(_)), Err(AdminError::UserNotFound)]
+        ));
+        assert!(is_admin(user(1)) && !is_admin(user(3)));
+
+        as_caller(user(3));
+        let results = bulk_set_role(vec![(user(3), Role::Admin), (user(1), Role::User)]);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| matches!(r, Err(AdminError::NotAuthorized))));
+        assert!(is_admin(user(1)) && !is_admin(user(3)));
+    }
+
+    #[test]
+    fn state_saved_before_grant_metadata_migrates_with_unknown_grants() {
+        let legacy = LegacyState {
+            users: BTreeMap::new(),
+            ideas: BTreeMap::new(),
+            next_idea_id: 1,
+            admins: BTreeSet::from([user(1)]),
+            admin_log: None,
+        };
+        let mut bytes = candid::encode_args((legacy,)).unwrap();
+        bytes.resize(bytes.len() + 64, 0); // stable memory is read back in whole pages
+        let state = decode_saved_state(&bytes).unwrap();
+        STATE.with(|s| *s.borrow_mut() = state);
+
+        let admins = get_admins();
+        assert_eq!(admins.len(), 1);
+        assert_eq!((admins[0].principal, admins[0].granted_at_ns, admins[0].granted_by), (user(1), 0, Principal::anonymous()));
+        assert!(is_admin(user(1)));
+        assert!(decode_saved_state(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn saved_state_from_a_newer_schema_is_refused_on_upgrade() {
+        assert_eq!(check_schema_version(SCHEMA_VERSION), Ok(()));
+        assert_eq!(check_schema_version(0), Ok(()), "saved before versioning");
+        assert_eq!(
+            check_schema_version(SCHEMA_VERSION + 1),
+            Err(format!("stable data uses schema v{}, newer than v{}; refusing to downgrade", SCHEMA_VERSION + 1, SCHEMA_VERSION))
+        );
+    }
+}
@@ -5,18 +5,43 @@
 
 use std::{borrow::Cow, cell::RefCell};
 
-use candid::{CandidType, Decode, Encode, Deserialize};
+use candid::{CandidType, Decode, Encode, Deserialize, Principal};
 use ic_cdk::{self};
-use ic_cdk_macros::{init, query, update};
+use ic_cdk_macros::{init, inspect_message, post_upgrade, query, update};
 
 // ---- Stable storage (Ideas) ----
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
-use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, storable::Bound , Storable};
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell, storable::Bound , Storable};
 use std::collections::HashMap;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
 const MAX_VALUE_SIZE: u32 = 2000;
+const CANISTER_VERSION: &str = "backend-canister-v1";
+const SCHEMA_VERSION: u32 = 1; // layout of the stable maps, see `migrate_schema`
+const CANDID_SCHEMA: &str = include_str!("../FundVerse_backend.did");
+const MAX_PAGE_LIMIT: u64 = 100; // upper bound for `limit` on paged listings
+const DEFAULT_MIN_TITLE_LEN: u64 = 3;
+const DEFAULT_MIN_DESCRIPTION_LEN: u64 = 50;
+const MAX_REASON_LEN: usize = 500;
+const MAX_BATCH_SIZE: usize = 100; // ids accepted by batch lookups
+const MAX_DOCS_PER_IDEA: usize = 20;
+const MAX_DOC_REPLY_BYTES: usize = 1_500_000; // keep document replies under the 2 MiB message limit
+const MAX_CAMPAIGN_LABELS: usize = 5;
+const MAX_LABEL_LEN: usize = 32;
+const MAX_UPDATE_TITLE_LEN: usize = 120; // bytes; title + body must fit `MAX_VALUE_SIZE`
+const MAX_UPDATE_BODY_LEN: usize = 1_500;
+const MIN_SLUG_LEN: usize = 3;
+const MAX_SLUG_LEN: usize = 64;
+const DEFAULT_MAX_ACTIVE_CAMPAIGNS_PER_IDEA: u64 = 1;
+const DEFAULT_MIN_CAMPAIGN_DURATION_SECS: u64 = 24 * 60 * 60; // 1 day
+const DEFAULT_MAX_CAMPAIGN_DURATION_SECS: u64 = 180 * 24 * 60 * 60; // ~6 months
+const DEFAULT_MIN_FUNDING_GOAL: u64 = 1_000_000; // 0.01 ICP in e8s
+const DEFAULT_MAX_FUNDING_GOAL: u64 = 1_000_000_000_000_000; // 10M ICP in e8s
+const MAX_DECIMALS: u8 = 18;
+const MAX_TAX_RATE_BPS: u16 = 10_000;
+const DUPLICATE_CAMPAIGN_WINDOW_SECS: u64 = 10; // repeat create_campaign calls inside this window are deduplicated
+const IDEA_STATUSES: [&str; 4] = ["pending", "approved", "rejected", "funded"];
 
 // Global memory manager + stable map for ideas
 thread_local! {
@@ -24,8 +49,8 @@ thread_local! {
         RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
 
     static DOCS: std::cell::RefCell<HashMap<u64, Doc>> = Default::default();
-    static IDEA_COUNTER: std::cell::RefCell<u64> = std::cell::RefCell::new(0);
-    static DOC_COUNTER: std::cell::RefCell<u64> = std::cell::RefCell::new(0);
+    static IDEA_COUNTER: std::cell::RefCell<u64> = const { std::cell::RefCell::new(0) };
+    static DOC_COUNTER: std::cell::RefCell<u64> = const { std::cell::RefCell::new(0) };
 
     static IDEAS: RefCell<StableBTreeMap<u64, Idea, Memory>> = RefCell::new(
         // Use memory 0 for ideas map
@@ -34,15 +59,132 @@ thread_local! {
         )
     );
 
-    // In-heap vector for campaigns (simple MVP). You can move this to stable later if needed.
-    static CAMPAIGNS: RefCell<Vec<Campaign>> = RefCell::new(Vec::new());
-    
-    // ICP contributions tracking: campaign_id -> total ICP amount in e8s
-    static ICP_CONTRIBUTIONS: RefCell<HashMap<u64, u64>> = RefCell::new(HashMap::new());
+    // Canister owner (the installer), set in `init`. Memory 1.
+    static OWNER: RefCell<StableCell<Principal, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(1))),
+            Principal::anonymous(),
+        ).expect("init owner cell")
+    );
+
+    // Minimum idea title/description lengths in chars, owner-tunable. Memories 2 and 3.
+    static MIN_TITLE_LEN: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(2))),
+            DEFAULT_MIN_TITLE_LEN,
+        ).expect("init min title cell")
+    );
+    static MIN_DESCRIPTION_LEN: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(3))),
+            DEFAULT_MIN_DESCRIPTION_LEN,
+        ).expect("init min description cell")
+    );
+
+    // Emergency pause: when true, non-owner state-changing calls are rejected. Memory 5.
+    static PAUSED: RefCell<StableCell<bool, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(5))),
+            false,
+        ).expect("init paused cell")
+    );
+
+    // How many running campaigns one idea may have at a time, owner-tunable. Memory 6.
+    static MAX_ACTIVE_CAMPAIGNS_PER_IDEA: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(6))),
+            DEFAULT_MAX_ACTIVE_CAMPAIGNS_PER_IDEA,
+        ).expect("init max active campaigns cell")
+    );
+
+    // Allowed campaign length (end_date - creation time) in seconds, owner-tunable. Memories 8 and 9.
+    static MIN_CAMPAIGN_DURATION_SECS: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(8))),
+            DEFAULT_MIN_CAMPAIGN_DURATION_SECS,
+        ).expect("init min duration cell")
+    );
+    static MAX_CAMPAIGN_DURATION_SECS: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(9))),
+            DEFAULT_MAX_CAMPAIGN_DURATION_SECS,
+        ).expect("init max duration cell")
+    );
+    static MIN_FUNDING_GOAL: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(10))),
+            DEFAULT_MIN_FUNDING_GOAL,
+        ).expect("init min funding goal cell")
+    );
+    static MAX_FUNDING_GOAL: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(11))),
+            DEFAULT_MAX_FUNDING_GOAL,
+        ).expect("init max funding goal cell")
+    );
+    // Schema version the stable data was written with (0 = before versioning). Memory 12.
+    static STORED_SCHEMA_VERSION: RefCell<StableCell<u32, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(12))),
+            0,
+        ).expect("init schema version cell")
+    );
+    // The Fund_Flow canister allowed to call the `receive_*` notifications (anonymous = unset). Memory 14.
+    static FUND_FLOW: RefCell<StableCell<Principal, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(14))),
+            Principal::anonymous(),
+        ).expect("init fund flow cell")
+    );
+    // Campaign slugs for shareable links: slug -> campaign_id. Memory 15.
+    static CAMPAIGN_SLUGS: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(15))))
+    );
+    // Live campaigns: campaign_id -> Campaign. Memory 16.
+    static CAMPAIGNS: RefCell<StableBTreeMap<u64, Campaign, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(16))))
+    );
+
+    // Settled campaigns moved out of the live listings: campaign_id -> Campaign. Memory 13.
+    static ARCHIVED_CAMPAIGNS: RefCell<StableBTreeMap<u64, Campaign, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(13))))
+    );
+
+    // Owner-curated featured campaigns: curation seq -> campaign_id, iterated in curation order. Memory 7.
+    static FEATURED: RefCell<StableBTreeMap<u64, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(7))))
+    );
+
+    // Audit trail of manual owner corrections: action_id -> AdminAction. Memory 4.
+    static ADMIN_ACTIONS: RefCell<StableBTreeMap<u64, AdminAction, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(4)))
+        )
+    );
+    // Creator posts for backers: update_id -> CampaignUpdate. Memory 17.
+    static CAMPAIGN_UPDATES: RefCell<StableBTreeMap<u64, CampaignUpdate, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(17))))
+    );
+
+    // ICP contributions tracking: campaign_id -> total ICP amount in e8s. Memory 18.
+    static ICP_CONTRIBUTIONS: RefCell<StableBTreeMap<u64, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(18))))
+    );
+
+    // Last create_campaign per caller. Heap only; it only has to outlive a double click.
+    static RECENT_CAMPAIGN_CREATIONS: RefCell<HashMap<Principal, RecentCreation>> =
+        RefCell::new(HashMap::new());
 }
 
 // ------------- Data Models -------------
 
+/// A caller's last `create_campaign`, kept to answer an identical retry with the same id.
+struct RecentCreation {
+    fingerprint: (u64, u64, u64), // (idea_id, goal, end_date)
+    campaign_id: u64,
+    created_at: u64,
+}
+
 #[derive(CandidType, Deserialize, serde::Serialize, Clone, Debug)]
 pub struct Idea {
     pub title: String,
@@ -50,13 +192,15 @@ pub struct Idea {
     pub funding_goal: u64,
     pub current_funding: u64,
     pub legal_entity: String,
-    pub status: Option<String>, // e.g., "pending", "approved", "rejected"
+    pub status: Option<String>, // one of `IDEA_STATUSES`
     pub contact_info: String,
     pub category: String,       // e.g., "technology", "healthcare", "education"
     pub business_registration: u8,
     pub created_at: u64,        // ns since epoch
     pub updated_at: u64,        // ns since epoch
     pub doc_ids: Vec<u64>,      // IDs of uploaded documents
+    pub owner: Option<Principal>, // submitter; `None` for ideas created before this was tracked
+    pub priority: Option<i32>,    // owner-set listing boost, higher first; `None` counts as 0
 }
 
 #[derive(CandidType, Deserialize, Clone)]
@@ -90,9 +234,39 @@ impl Storable for Idea {
 pub struct Campaign {
     pub id: u64,
     pub idea_id: u64,      // 🔗 foreign key to Idea
+    pub creator: Principal,
     pub amount_raised: u64,
     pub goal: u64,
     pub end_date: u64,     // seconds since Unix epoch
+    pub labels: Vec<String>, // marketing labels, e.g. "Early Bird"
+    pub owner_wallet: Option<Principal>, // where Fund_Flow pays out released funds
+    pub paid_out_at: Option<u64>,        // seconds since epoch, set by `receive_payout`
+    // Unit of `goal`/`amount_raised`: smallest unit of `currency` with `decimals` digits
+    // (ICP with 8 decimals = e8s). None until `set_campaign_currency`: legacy units, where
+    // Fund_Flow counts every payment method's amount as-is.
+    pub currency: Option<String>,
+    pub decimals: Option<u8>,
+    pub backer_count: u64, // distinct ICP backers, as reported by Fund_Flow
+    pub tax_rate_bps: u16, // VAT included in contributions, in basis points; 0 = not tax-applicable
+    pub combined_max_per_backer: Option<u64>, // cap on one backer's total across all methods, in goal units
+    pub slug: Option<String>, // unique URL handle chosen at creation, see `normalize_slug`
+    pub status: Option<CampaignStatus>, // stored lifecycle; None for campaigns created before it, see `campaign_status`
+    pub closed_at: Option<u64>, // seconds since epoch, set by `close_campaign_early`
+}
+
+impl Storable for Campaign {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("encode Campaign"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("decode Campaign")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_VALUE_SIZE,
+        is_fixed_size: false,
+    };
 }
 
 #[derive(CandidType, Deserialize, Clone, Debug)]
@@ -105,12 +279,58 @@ pub struct CampaignCard {
     pub goal: u64,
     pub end_date: u64,
     pub days_left: u64,    // negative => ended
+    pub labels: Vec<String>,
+    pub backer_count: u64,
+    pub slug: Option<String>,
+}
+
+/// Lightweight idea listing entry; carries the document count instead of the ids.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IdeaSummary {
+    pub idea_id: u64,
+    pub title: String,
+    pub category: String,
+    pub status: Option<String>,
+    pub funding_goal: u64,
+    pub current_funding: u64,
+    pub doc_count: u64,
+    pub priority: i32,
+}
+
+/// One idea of a founder's portfolio with its campaigns. `total_raised` sums the campaigns'
+/// `amount_raised` as-is, so it only means one amount when they share a currency.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IdeaPortfolioEntry {
+    pub idea_id: u64,
+    pub idea: Idea,
+    pub campaigns: Vec<CampaignCard>,
+    pub total_raised: u64,
+}
+
+/// One page of campaign cards; `next_offset` is `None` on the last page.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CampaignCardPage {
+    pub items: Vec<CampaignCard>,
+    pub total: u64,
+    pub next_offset: Option<u64>,
 }
 
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub enum CampaignStatus {
     Active,
     Ended,
+    PaidOut,  // Fund_Flow released the funds, see `receive_payout`
+    Refunded, // Fund_Flow refunded the backers, see `receive_refund`
+}
+
+/// Sort key for `get_campaign_cards_sorted`.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug)]
+pub enum CardSort {
+    DaysLeft,
+    AmountRaised,
+    ProgressBps, // amount_raised / goal in basis points, uncapped
+    EndDate,
+    Priority,    // the linked idea's priority
 }
 
 #[derive(CandidType, Deserialize, Clone, Debug)]
@@ -126,13 +346,124 @@ pub struct CampaignMeta {
     pub goal: u64,
     pub amount_raised: u64,
     pub end_date_secs: u64, // seconds since epoch
+    pub owner_wallet: Option<Principal>,
+    pub currency: Option<String>, // unit of `goal`/`amount_raised`, see `Campaign`
+    pub decimals: Option<u8>,
+    pub tax_rate_bps: u16,
+    pub combined_max_per_backer: Option<u64>,
+    pub creator: Principal, // lets Fund_Flow gate creator-only views
+    pub closed_at: Option<u64>, // closed early by the creator; Fund_Flow skips the settlement grace
+}
+
+/// Audit entry for a manual correction made by the owner.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AdminAction {
+    pub id: u64,
+    pub by: Principal,
+    pub action: String,     // e.g. "adjust_campaign_amount"
+    pub campaign_id: u64,
+    pub old_value: u64,
+    pub new_value: u64,
+    pub reason: String,
+    pub at_ns: u64,
+}
+
+impl Storable for AdminAction {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("encode AdminAction"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("decode AdminAction")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_VALUE_SIZE,
+        is_fixed_size: false,
+    };
+}
+
+/// A project update posted by the campaign creator (or owner) for backers.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CampaignUpdate {
+    pub id: u64,
+    pub campaign_id: u64,
+    pub title: String,
+    pub body: String,
+    pub posted_at_ns: u64,
+}
+
+impl Storable for CampaignUpdate {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("encode CampaignUpdate"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("decode CampaignUpdate")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_VALUE_SIZE,
+        is_fixed_size: false,
+    };
+}
+
+/// Result of `verify_campaign_consistency`: Fund_Flow's escrow view against this canister's.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ConsistencyReport {
+    pub campaign_id: u64,
+    pub fund_flow_total: u64,       // held + released in Fund_Flow
+    pub backend_amount_raised: u64,
+    pub backend_icp_recorded: u64,  // ICP_CONTRIBUTIONS entry
+    pub delta: i64,                 // backend_amount_raised - fund_flow_total
+    pub consistent: bool,
+}
+
+/// How a campaign's `amount_raised` splits between on-chain ICP and off-chain payments.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct FundingBreakdown {
+    pub icp_e8s: u64,
+    pub fiat_amount: u64,
+    pub total: u64,
+}
+
+/// Returned by `get_version`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct VersionInfo {
+    pub version: String,
+    pub candid_schema_hash: String, // FNV-1a of FundVerse_backend.did, hex
+    pub build_time: Option<String>, // BUILD_TIME at compile time
+}
+
+/// The part of Fund_Flow's `EscrowSummary` the consistency and settlement checks read.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct FundFlowEscrowSummary {
+    campaign_id: u64,
+    total_held: u64,
+    total_released: u64,
 }
 
 // ------------- Helpers -------------
+// The system API panics off-wasm, so unit tests read the clock and caller from `mock`.
+#[cfg(not(test))]
+fn now_ns() -> u64 {
+    ic_cdk::api::time()
+}
+#[cfg(test)]
+fn now_ns() -> u64 {
+    mock::NOW_NS.with(|n| n.get())
+}
+#[cfg(not(test))]
+fn caller() -> Principal {
+    ic_cdk::caller()
+}
+#[cfg(test)]
+fn caller() -> Principal {
+    mock::CALLER.with(|c| c.get())
+}
 
 fn now_secs() -> u64 {
-    // ic_cdk::api::time returns ns
-    ic_cdk::api::time() / 1_000_000_000
+    now_ns() / 1_000_000_000
 }
 
 fn to_card(c: &Campaign, idea: &Idea) -> CampaignCard {
@@ -149,61 +480,213 @@ fn to_card(c: &Campaign, idea: &Idea) -> CampaignCard {
         goal: c.goal,
         end_date: c.end_date,
         days_left,
+        labels: c.labels.clone(),
+        backer_count: c.backer_count,
+        slug: c.slug.clone(),
+    }
+}
+
+fn owner() -> Principal {
+    OWNER.with(|o| *o.borrow().get())
+}
+
+/// Stable data written before the owner cell existed leaves it anonymous, which would lock every
+/// owner-only endpoint; the controller performing the upgrade claims it instead.
+fn seed_owner_on_upgrade() {
+    if owner() == Principal::anonymous() {
+        let upgrader = caller();
+        OWNER.with(|o| o.borrow_mut().set(upgrader).expect("set owner"));
+        ic_cdk::println!("owner unset; claimed by upgrading controller {}", upgrader);
+    }
+}
+
+fn ensure_owner() -> Result<(), String> {
+    if caller() == owner() {
+        Ok(())
+    } else {
+        Err("only the owner can do this".into())
+    }
+}
+
+/// Whether `p` is a canister id rather than a user principal: canister ids are 10-byte opaque ids
+/// (type byte 0x01), while self-authenticating user principals end in 0x02.
+fn is_canister_principal(p: &Principal) -> bool {
+    let bytes = p.as_slice();
+    bytes.len() == 10 && bytes[9] == 0x01
+}
+
+/// Gate for the notifications Fund_Flow sends after moving escrowed funds. The caller must be
+/// the registered Fund_Flow canister id and look like a canister; `inspect_message` also drops
+/// ingress to these methods, but that filter is advisory, so this check is the one that counts.
+fn ensure_fund_flow() -> Result<(), String> {
+    let caller = caller();
+    let fund_flow = FUND_FLOW.with(|c| *c.borrow().get());
+    if fund_flow == Principal::anonymous() {
+        return Err("Fund_Flow canister not configured".into());
+    }
+    if caller != fund_flow || !is_canister_principal(&caller) {
+        return Err("only the Fund_Flow canister can do this".into());
+    }
+    Ok(())
+}
+
+fn ensure_not_paused() -> Result<(), String> {
+    if is_paused() {
+        Err("canister paused".into())
+    } else {
+        Ok(())
+    }
+}
+
+fn ensure_owner_or_creator(c: &Campaign) -> Result<(), String> {
+    let caller = caller();
+    if caller == c.creator || caller == owner() {
+        Ok(())
+    } else {
+        Err("only the campaign creator or owner can do this".into())
+    }
+}
+
+fn ensure_owner_or_submitter(idea: &Idea) -> Result<(), String> {
+    let caller = caller();
+    if idea.owner == Some(caller) || caller == owner() {
+        Ok(())
+    } else {
+        Err("only the idea's submitter or owner can do this".into())
+    }
+}
+
+fn log_admin_action(action: &str, campaign_id: u64, old_value: u64, new_value: u64, reason: String) {
+    ADMIN_ACTIONS.with(|log| {
+        let mut log = log.borrow_mut();
+        let id = log.last_key_value().map(|(k, _)| k + 1).unwrap_or(1);
+        log.insert(id, AdminAction {
+            id,
+            by: caller(),
+            action: action.to_string(),
+            campaign_id,
+            old_value,
+            new_value,
+            reason,
+            at_ns: now_ns(),
+        });
+    });
+}
+
+/// Trim labels and check count/length limits.
+fn normalize_labels(labels: Vec<String>) -> Result<Vec<String>, String> {
+    if labels.len() > MAX_CAMPAIGN_LABELS {
+        return Err(format!("at most {} labels allowed", MAX_CAMPAIGN_LABELS));
     }
+    labels
+        .into_iter()
+        .map(|l| {
+            let l = l.trim().to_string();
+            if l.is_empty() || l.chars().count() > MAX_LABEL_LEN {
+                Err(format!("labels must be 1-{} characters", MAX_LABEL_LEN))
+            } else {
+                Ok(l)
+            }
+        })
+        .collect()
 }
 
 
+/// Whether the candid-encoded value stays within the `MAX_VALUE_SIZE` bound of the stable maps.
+/// Check before inserting anything that can grow; an oversized insert traps.
+fn fits_value_bound<T: CandidType>(value: &T) -> bool {
+    Encode!(value).map(|b| b.len() <= MAX_VALUE_SIZE as usize).unwrap_or(false)
+}
+
 fn get_idea(id: u64) -> Option<Idea> {
     IDEAS.with(|map| map.borrow().get(&id))
 }
 
 fn get_campaign(id: u64) -> Option<Campaign> {
+    CAMPAIGNS.with(|store| store.borrow().get(&id))
+}
+
+/// A live or archived campaign, for read paths that must keep resolving historical links.
+fn find_campaign(id: u64) -> Option<Campaign> {
+    get_campaign(id).or_else(|| ARCHIVED_CAMPAIGNS.with(|m| m.borrow().get(&id)))
+}
+
+/// An idea's live and archived campaigns, ordered by campaign id.
+fn idea_campaigns(idea_id: u64) -> Vec<Campaign> {
+    let of_idea = |(_, c): (u64, Campaign)| (c.idea_id == idea_id).then_some(c);
+    let mut campaigns: Vec<Campaign> = CAMPAIGNS.with(|store| store.borrow().iter().filter_map(of_idea).collect());
+    campaigns.extend(ARCHIVED_CAMPAIGNS.with(|m| m.borrow().iter().filter_map(of_idea).collect::<Vec<_>>()));
+    campaigns.sort_by_key(|c| c.id);
+    campaigns
+}
+
+fn update_campaign(campaign_id: u64, f: impl FnOnce(&mut Campaign)) {
     CAMPAIGNS.with(|store| {
-        store.borrow().iter().find(|c| c.id == id).cloned()
-    })
+        let mut store = store.borrow_mut();
+        if let Some(mut campaign) = store.get(&campaign_id) {
+            f(&mut campaign);
+            store.insert(campaign_id, campaign);
+        }
+    });
 }
 
 fn update_campaign_amount(campaign_id: u64, new_amount: u64) {
-    CAMPAIGNS.with(|store| {
-        if let Some(campaign) = store.borrow_mut().iter_mut().find(|c| c.id == campaign_id) {
-            campaign.amount_raised = new_amount;
+    update_campaign(campaign_id, |c| c.amount_raised = new_amount);
+}
+
+/// Reset an idea's `current_funding` to the sum of its campaigns' `amount_raised`, so it stays
+/// an aggregate no matter which campaign moved. Archived campaigns still count; refunded ones
+/// gave their money back and don't.
+fn recompute_idea_funding(idea_id: u64) {
+    let total = idea_campaigns(idea_id)
+        .iter()
+        .filter(|c| !matches!(c.status, Some(CampaignStatus::Refunded)))
+        .fold(0u64, |acc, c| acc.saturating_add(c.amount_raised));
+    IDEAS.with(|ideas| {
+        let current = ideas.borrow().get(&idea_id);
+        if let Some(mut idea) = current {
+            idea.current_funding = total;
+            idea.updated_at = now_ns();
+            ideas.borrow_mut().insert(idea_id, idea);
         }
     });
 }
 
-/// Upload a document for an Idea. Returns the new doc_id or None if idea doesn't exist.
+/// Upload a document for an Idea. Returns the new doc_id, or an error if the idea doesn't exist
+/// or already holds `MAX_DOCS_PER_IDEA` documents.
 #[update]
-fn upload_doc(idea_id: u64, name: String, content_type: String, data: Vec<u8>, uploaded_at: u64) -> Option<u64> {
-    if !IDEAS.with(|ideas| ideas.borrow().contains_key(&idea_id)) {
-        return None; // idea doesn't exist
+fn upload_doc(idea_id: u64, name: String, content_type: String, data: Vec<u8>, uploaded_at: u64) -> Result<u64, String> {
+    ensure_not_paused()?;
+    let Some(mut idea) = get_idea(idea_id) else {
+        return Err("idea_id not found".into());
+    };
+    if idea.doc_ids.len() >= MAX_DOCS_PER_IDEA {
+        return Err(format!("an idea can have at most {} documents", MAX_DOCS_PER_IDEA));
     }
 
-    DOC_COUNTER.with(|c| {
-        let mut c = c.borrow_mut();
-        *c += 1;
-        let doc_id = *c;
-
-        let doc = Doc {
-            id: doc_id,
-            idea_id,
-            name,
-            content_type,
-            data,
-            uploaded_at,
-        };
+    // doc_ids lives inside the bounded Idea entry, so make sure it still fits before the counter
+    // advances; a rejected upload must not consume an id
+    let doc_id = DOC_COUNTER.with(|c| *c.borrow()) + 1;
+    idea.doc_ids.push(doc_id);
+    if !fits_value_bound(&idea) {
+        return Err("idea record is full; no more documents can be attached".into());
+    }
+    DOC_COUNTER.with(|c| *c.borrow_mut() = doc_id);
 
-        DOCS.with(|docs| docs.borrow_mut().insert(doc_id, doc));
+    let doc = Doc {
+        id: doc_id,
+        idea_id,
+        name,
+        content_type,
+        data,
+        uploaded_at,
+    };
+    DOCS.with(|docs| docs.borrow_mut().insert(doc_id, doc));
 
-        // attach to idea
-        IDEAS.with(|ideas| {
-            if let Some(mut idea) = ideas.borrow().get(&idea_id) {
-                idea.doc_ids.push(doc_id);
-                ideas.borrow_mut().insert(idea_id, idea);
-            }
-        });
+    // attach to idea
+    IDEAS.with(|ideas| ideas.borrow_mut().insert(idea_id, idea));
 
-        Some(doc_id)
-    })
+    Ok(doc_id)
 }
 
 // ------------- Public API -------------
@@ -218,20 +701,39 @@ fn create_idea(
     contact_info: String,
     category: String,
     business_registration: u8,
-) -> u64 {
-    if title.is_empty()
-        || description.is_empty()
-        || funding_goal == 0
-        || legal_entity.is_empty()
-        || contact_info.is_empty()
-        || category.is_empty()
-    {
-        ic_cdk::trap(
-            "Invalid input: all fields must be provided and funding_goal must be > 0.",
-        );
+) -> Result<u64, String> {
+    ensure_not_paused()?;
+    if title.is_empty() {
+        return Err("title is required".into());
+    }
+    if description.is_empty() {
+        return Err("description is required".into());
+    }
+    if funding_goal == 0 {
+        return Err("funding_goal must be > 0".into());
+    }
+    let (min_goal, max_goal) = get_funding_goal_bounds();
+    if funding_goal < min_goal || funding_goal > max_goal {
+        return Err(format!("funding_goal must be between {} and {}", min_goal, max_goal));
+    }
+    if legal_entity.is_empty() {
+        return Err("legal_entity is required".into());
+    }
+    if contact_info.is_empty() {
+        return Err("contact_info is required".into());
+    }
+    if category.is_empty() {
+        return Err("category is required".into());
+    }
+    let (min_title, min_description) = get_idea_text_minimums();
+    if (title.trim().chars().count() as u64) < min_title {
+        return Err(format!("title must be at least {} characters", min_title));
+    }
+    if (description.trim().chars().count() as u64) < min_description {
+        return Err(format!("description must be at least {} characters", min_description));
     }
 
-    let now = ic_cdk::api::time();
+    let now = now_ns();
     let idea = Idea {
         title,
         description,
@@ -241,25 +743,146 @@ fn create_idea(
         status: Some("pending".to_string()),
         contact_info,
         doc_ids : vec![],
+        owner: Some(caller()),
+        priority: None,
         category,
         business_registration,
         created_at: now,
         updated_at: now,
     };
+    if !fits_value_bound(&idea) {
+        return Err(format!("idea is too large to store (max {} bytes encoded)", MAX_VALUE_SIZE));
+    }
 
     // naive id generation = len + 1 (OK for MVP)
     // consider a StableCell counter for production.
-    IDEAS.with(|ideas| {
+    let id = IDEAS.with(|ideas| {
         let mut ideas = ideas.borrow_mut();
-        let id = (ideas.len() as u64) + 1;
+        let id = ideas.len() + 1;
         ideas.insert(id, idea);
         id
-    })
+    });
+    Ok(id)
+}
+
+/// Move an idea to another review status (owner only). Only the statuses in `IDEA_STATUSES`
+/// are accepted, so a typo like "aproved" is rejected instead of stored.
+#[update]
+fn set_idea_status(idea_id: u64, status: String) -> Result<(), String> {
+    ensure_owner()?;
+    let status = status.trim().to_lowercase();
+    if !IDEA_STATUSES.contains(&status.as_str()) {
+        return Err("invalid status".into());
+    }
+    let mut idea = get_idea(idea_id).ok_or_else(|| "idea_id not found".to_string())?;
+    idea.status = Some(status);
+    idea.updated_at = now_ns();
+    IDEAS.with(|ideas| ideas.borrow_mut().insert(idea_id, idea));
+    Ok(())
+}
+
+/// Boost an idea in priority-sorted listings; higher comes first, 0 is the default (owner only).
+#[update]
+fn set_idea_priority(idea_id: u64, priority: i32) -> Result<(), String> {
+    ensure_owner()?;
+    let mut idea = get_idea(idea_id).ok_or_else(|| "idea_id not found".to_string())?;
+    idea.priority = Some(priority);
+    idea.updated_at = now_ns();
+    IDEAS.with(|ideas| ideas.borrow_mut().insert(idea_id, idea));
+    Ok(())
+}
+
+/// Minimum idea (title, description) lengths in characters enforced by `create_idea`.
+#[query]
+fn get_idea_text_minimums() -> (u64, u64) {
+    (
+        MIN_TITLE_LEN.with(|c| *c.borrow().get()),
+        MIN_DESCRIPTION_LEN.with(|c| *c.borrow().get()),
+    )
+}
+
+/// Tune the minimum idea title/description lengths (owner only).
+#[update]
+fn set_idea_text_minimums(min_title_len: u64, min_description_len: u64) -> Result<(), String> {
+    ensure_owner()?;
+    MIN_TITLE_LEN.with(|c| c.borrow_mut().set(min_title_len).expect("set min title"));
+    MIN_DESCRIPTION_LEN.with(|c| c.borrow_mut().set(min_description_len).expect("set min description"));
+    Ok(())
+}
+
+#[query]
+fn get_max_active_campaigns_per_idea() -> u64 {
+    MAX_ACTIVE_CAMPAIGNS_PER_IDEA.with(|c| *c.borrow().get())
+}
+
+/// Set how many active campaigns an idea may run at once (owner only, at least 1).
+#[update]
+fn set_max_active_campaigns_per_idea(max: u64) -> Result<(), String> {
+    ensure_owner()?;
+    if max == 0 {
+        return Err("max must be at least 1".into());
+    }
+    MAX_ACTIVE_CAMPAIGNS_PER_IDEA.with(|c| c.borrow_mut().set(max).expect("set max active campaigns"));
+    Ok(())
+}
+
+/// Allowed idea `funding_goal` as (min, max), inclusive.
+#[query]
+fn get_funding_goal_bounds() -> (u64, u64) {
+    (
+        MIN_FUNDING_GOAL.with(|c| *c.borrow().get()),
+        MAX_FUNDING_GOAL.with(|c| *c.borrow().get()),
+    )
+}
+
+/// Set the allowed idea funding goal range (owner only). Existing ideas are not re-checked.
+#[update]
+fn set_funding_goal_bounds(min_goal: u64, max_goal: u64) -> Result<(), String> {
+    ensure_owner()?;
+    if min_goal == 0 || min_goal > max_goal {
+        return Err("bounds must satisfy 0 < min <= max".into());
+    }
+    MIN_FUNDING_GOAL.with(|c| c.borrow_mut().set(min_goal).expect("set min funding goal"));
+    MAX_FUNDING_GOAL.with(|c| c.borrow_mut().set(max_goal).expect("set max funding goal"));
+    Ok(())
+}
+
+/// Allowed campaign length as (min, max) seconds.
+#[query]
+fn get_campaign_duration_bounds() -> (u64, u64) {
+    (
+        MIN_CAMPAIGN_DURATION_SECS.with(|c| *c.borrow().get()),
+        MAX_CAMPAIGN_DURATION_SECS.with(|c| *c.borrow().get()),
+    )
+}
+
+/// Tune the allowed campaign length in seconds (owner only).
+#[update]
+fn set_campaign_duration_bounds(min_secs: u64, max_secs: u64) -> Result<(), String> {
+    ensure_owner()?;
+    if max_secs == 0 || min_secs > max_secs {
+        return Err("bounds must satisfy min <= max and max > 0".into());
+    }
+    MIN_CAMPAIGN_DURATION_SECS.with(|c| c.borrow_mut().set(min_secs).expect("set min duration"));
+    MAX_CAMPAIGN_DURATION_SECS.with(|c| c.borrow_mut().set(max_secs).expect("set max duration"));
+    Ok(())
+}
+
+/// Still collecting: not past its end date, goal not yet met and not paid out.
+fn is_campaign_active(c: &Campaign, now: u64) -> bool {
+    c.end_date > now && c.amount_raised < c.goal && c.paid_out_at.is_none()
 }
 
 /// Create a Campaign linked to an existing Idea. Returns new campaign_id (Ok) or error (Err).
 #[update]
-fn create_campaign(idea_id: u64, goal: u64, end_date: u64) -> Result<u64, String> {
+fn create_campaign(
+    idea_id: u64,
+    goal: u64,
+    end_date: u64,
+    labels: Vec<String>,
+    slug: Option<String>,
+) -> Result<u64, String> {
+    ensure_not_paused()?;
     if goal == 0 {
         return Err("goal must be > 0".into());
     }
@@ -267,70 +890,449 @@ fn create_campaign(idea_id: u64, goal: u64, end_date: u64) -> Result<u64, String
     let Some(_idea) = get_idea(idea_id) else {
         return Err("idea_id not found".into());
     };
+    let now = now_secs();
+    let creator = caller();
+    let (min_secs, max_secs) = get_campaign_duration_bounds();
+    let duration = end_date.saturating_sub(now);
+    if duration == 0 || duration < min_secs || duration > max_secs {
+        return Err(format!("campaign must run between {} and {} seconds", min_secs, max_secs));
+    }
+    let labels = normalize_labels(labels)?;
+    let slug = slug.map(normalize_slug).transpose()?;
+    // only a valid request can be a retry; checked before the cap and the slug, which the
+    // original campaign already takes up
+    let fingerprint = (idea_id, goal, end_date);
+    if let Some(existing) = recent_duplicate_campaign(creator, fingerprint, now) {
+        return Ok(existing);
+    }
+    let active = CAMPAIGNS.with(|store| {
+        store.borrow().iter().filter(|(_, c)| c.idea_id == idea_id && is_campaign_active(c, now)).count() as u64
+    });
+    if active >= get_max_active_campaigns_per_idea() {
+        return Err("idea already has an active campaign".into());
+    }
+    if let Some(slug) = &slug {
+        if CAMPAIGN_SLUGS.with(|m| m.borrow().contains_key(slug)) {
+            return Err("slug already taken".into());
+        }
+    }
 
+    // archived campaigns keep their ids, so count past them
+    let last_archived = ARCHIVED_CAMPAIGNS.with(|m| m.borrow().last_key_value().map(|(k, _)| k)).unwrap_or(0);
     let id = CAMPAIGNS.with(|store| {
-        let mut vec = store.borrow_mut();
-        let new_id = (vec.len() as u64) + 1;
-        vec.push(Campaign {
+        let mut store = store.borrow_mut();
+        let new_id = store.last_key_value().map(|(k, _)| k).unwrap_or(0).max(last_archived) + 1;
+        store.insert(new_id, Campaign {
             id: new_id,
             idea_id,
+            creator,
             amount_raised: 0,
             goal,
             end_date,
+            labels,
+            owner_wallet: None,
+            paid_out_at: None,
+            currency: None,
+            decimals: None,
+            backer_count: 0,
+            tax_rate_bps: 0,
+            combined_max_per_backer: None,
+            slug: slug.clone(),
+            status: Some(CampaignStatus::Active),
+            closed_at: None,
         });
         new_id
     });
+    if let Some(slug) = slug {
+        CAMPAIGN_SLUGS.with(|m| m.borrow_mut().insert(slug, id));
+    }
+    RECENT_CAMPAIGN_CREATIONS.with(|m| {
+        m.borrow_mut().insert(creator, RecentCreation { fingerprint, campaign_id: id, created_at: now })
+    });
 
     Ok(id)
 }
 
-/// Return all campaign cards (title/category pulled from linked Idea).
-#[query]
-fn get_campaign_cards() -> Vec<CampaignCard> {
-    CAMPAIGNS.with(|store| {
-        store
-            .borrow()
-            .iter()
-            .filter_map(|c| get_idea(c.idea_id).map(|idea| to_card(c, &idea)))
-            .collect()
+/// Id of the campaign `caller` just created with the same idea/goal/end date,
+/// if it happened within `DUPLICATE_CAMPAIGN_WINDOW_SECS`. Catches double clicks.
+fn recent_duplicate_campaign(caller: Principal, fingerprint: (u64, u64, u64), now: u64) -> Option<u64> {
+    RECENT_CAMPAIGN_CREATIONS.with(|m| {
+        let mut m = m.borrow_mut();
+        m.retain(|_, recent| now.saturating_sub(recent.created_at) <= DUPLICATE_CAMPAIGN_WINDOW_SECS);
+        m.get(&caller)
+            .filter(|recent| recent.fingerprint == fingerprint)
+            .map(|recent| recent.campaign_id)
     })
+    .filter(|id| get_campaign(*id).is_some())
 }
 
-///return docs with idea_id
+/// Create a Campaign ending `duration_secs` from now; same checks as `create_campaign`.
+#[update]
+fn create_campaign_with_duration(idea_id: u64, goal: u64, duration_secs: u64) -> Result<u64, String> {
+    create_campaign(idea_id, goal, now_secs().saturating_add(duration_secs), Vec::new(), None)
+}
+
+/// Trim and lowercase a campaign slug; it must be `MIN_SLUG_LEN`-`MAX_SLUG_LEN` characters of
+/// a-z, 0-9 and single hyphens, not starting or ending with a hyphen.
+fn normalize_slug(slug: String) -> Result<String, String> {
+    let slug = slug.trim().to_lowercase();
+    let well_formed = (MIN_SLUG_LEN..=MAX_SLUG_LEN).contains(&slug.len())
+        && slug.chars().all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '-')
+        && !slug.starts_with('-')
+        && !slug.ends_with('-')
+        && !slug.contains("--");
+    if well_formed {
+        Ok(slug)
+    } else {
+        Err(format!(
+            "slug must be {}-{} characters of a-z, 0-9 and single hyphens, not starting or ending with a hyphen",
+            MIN_SLUG_LEN, MAX_SLUG_LEN
+        ))
+    }
+}
+
+/// Look a campaign up by its slug (case-insensitive); archived campaigns are included.
 #[query]
-fn get_doc(doc_id: u64) -> Option<Doc> {
-    DOCS.with(|docs| docs.borrow().get(&doc_id).cloned())
+fn get_campaign_by_slug(slug: String) -> Option<CampaignCard> {
+    let id = CAMPAIGN_SLUGS.with(|m| m.borrow().get(&slug.trim().to_lowercase()))?;
+    let c = find_campaign(id)?;
+    get_idea(c.idea_id).map(|idea| to_card(&c, &idea))
 }
 
-/// Return cards filtered by status (Active/Ended).
+/// Return all campaign cards (title/category pulled from linked Idea).
 #[query]
-fn get_campaign_cards_by_status(status: CampaignStatus) -> Vec<CampaignCard> {
-    let now = now_secs() as i64;
+fn get_campaign_cards() -> Vec<CampaignCard> {
     CAMPAIGNS.with(|store| {
         store
             .borrow()
             .iter()
-            .filter_map(|c| get_idea(c.idea_id).map(|idea| to_card(c, &idea)))
-            .filter(|card| match status {
-                CampaignStatus::Active => card.days_left >= 0 && (card.end_date as i64) >= now,
-                CampaignStatus::Ended => card.days_left < 0 || (card.end_date as i64) < now,
-            })
+            .filter_map(|(_, c)| get_idea(c.idea_id).map(|idea| to_card(&c, &idea)))
             .collect()
     })
 }
 
-/// Fetch a single campaign joined with its Idea.
+/// All campaign cards ordered by `sort`; ties are broken by campaign id in the same direction.
 #[query]
-fn get_campaign_with_idea(campaign_id: u64) -> Option<CampaignWithIdea> {
-    CAMPAIGNS.with(|store| {
-        store
+fn get_campaign_cards_sorted(sort: CardSort, desc: bool) -> Vec<CampaignCard> {
+    let progress_bps = |card: &CampaignCard| (card.amount_raised as u128 * 10_000) / card.goal.max(1) as u128;
+    let mut cards = get_campaign_cards();
+    let priorities: HashMap<u64, i32> = cards
+        .iter()
+        .map(|card| (card.idea_id, get_idea(card.idea_id).and_then(|idea| idea.priority).unwrap_or(0)))
+        .collect();
+    let priority = |card: &CampaignCard| priorities[&card.idea_id];
+    cards.sort_by(|a, b| {
+        let primary = match sort {
+            CardSort::DaysLeft => a.days_left.cmp(&b.days_left),
+            CardSort::AmountRaised => a.amount_raised.cmp(&b.amount_raised),
+            CardSort::ProgressBps => progress_bps(a).cmp(&progress_bps(b)),
+            CardSort::EndDate => a.end_date.cmp(&b.end_date),
+            CardSort::Priority => priority(a).cmp(&priority(b)),
+        };
+        let order = primary.then(a.id.cmp(&b.id));
+        if desc { order.reverse() } else { order }
+    });
+    cards
+}
+
+/// Replace a campaign's marketing labels (creator or owner only).
+#[update]
+fn set_campaign_labels(campaign_id: u64, labels: Vec<String>) -> Result<(), String> {
+    ensure_not_paused()?;
+    let campaign = get_campaign(campaign_id).ok_or_else(|| "Campaign not found".to_string())?;
+    ensure_owner_or_creator(&campaign)?;
+    let labels = normalize_labels(labels)?;
+    update_campaign(campaign_id, |c| c.labels = labels);
+    Ok(())
+}
+
+/// Close a campaign before its deadline (creator or owner only).
+/// The end date becomes "now" and `closed_at` is recorded, so Fund_Flow stops accepting
+/// contributions immediately, without its settlement grace, and `release_campaign` can
+/// proceed if the goal was met.
+#[update]
+fn close_campaign_early(campaign_id: u64) -> Result<(), String> {
+    ensure_not_paused()?;
+    let campaign = get_campaign(campaign_id).ok_or_else(|| "Campaign not found".to_string())?;
+    ensure_owner_or_creator(&campaign)?;
+    let now = now_secs();
+    if campaign.end_date <= now {
+        return Err("campaign already ended".into());
+    }
+    update_campaign(campaign_id, |c| {
+        c.end_date = now;
+        c.closed_at = Some(now);
+    });
+    Ok(())
+}
+
+/// Return cards carrying the given label (case-insensitive).
+#[query]
+fn get_campaign_cards_by_label(label: String) -> Vec<CampaignCard> {
+    let label = label.trim().to_lowercase();
+    CAMPAIGNS.with(|store| {
+        store
             .borrow()
             .iter()
-            .find(|c| c.id == campaign_id)
-            .and_then(|c| get_idea(c.idea_id).map(|idea| CampaignWithIdea {
-                campaign: to_card(c, &idea),
+            .filter(|(_, c)| c.labels.iter().any(|l| l.to_lowercase() == label))
+            .filter_map(|(_, c)| get_idea(c.idea_id).map(|idea| to_card(&c, &idea)))
+            .collect()
+    })
+}
+
+/// Post an update to a campaign (creator or owner only). Title and body are trimmed; the title
+/// must be 1-`MAX_UPDATE_TITLE_LEN` bytes and the body 1-`MAX_UPDATE_BODY_LEN`.
+#[update]
+fn add_campaign_update(campaign_id: u64, title: String, body: String) -> Result<u64, String> {
+    ensure_not_paused()?;
+    let campaign = get_campaign(campaign_id).ok_or_else(|| "Campaign not found".to_string())?;
+    ensure_owner_or_creator(&campaign)?;
+    let title = title.trim().to_string();
+    let body = body.trim().to_string();
+    if title.is_empty() || title.len() > MAX_UPDATE_TITLE_LEN {
+        return Err(format!("title must be 1-{} bytes", MAX_UPDATE_TITLE_LEN));
+    }
+    if body.is_empty() || body.len() > MAX_UPDATE_BODY_LEN {
+        return Err(format!("body must be 1-{} bytes", MAX_UPDATE_BODY_LEN));
+    }
+    let id = CAMPAIGN_UPDATES.with(|m| {
+        let mut m = m.borrow_mut();
+        let id = m.last_key_value().map(|(k, _)| k + 1).unwrap_or(1);
+        m.insert(id, CampaignUpdate { id, campaign_id, title, body, posted_at_ns: now_ns() });
+        id
+    });
+    Ok(id)
+}
+
+/// Page through a campaign's updates, newest first.
+#[query]
+fn get_campaign_updates(campaign_id: u64, offset: u64, limit: u64) -> Vec<CampaignUpdate> {
+    CAMPAIGN_UPDATES.with(|m| {
+        m.borrow()
+            .iter()
+            .rev()
+            .map(|(_, u)| u)
+            .filter(|u| u.campaign_id == campaign_id)
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_LIMIT) as usize)
+            .collect()
+    })
+}
+
+/// Full documents of the caller's own idea, e.g. to download originals before cleanup
+/// (idea submitter or owner only). Starts at the `offset`-th document and stops before the
+/// reply would exceed `MAX_DOC_REPLY_BYTES`; call again with `offset + len` for the rest.
+/// A single document larger than the budget is still returned on its own.
+#[query]
+fn get_my_idea_docs(idea_id: u64, offset: u64) -> Result<Vec<Doc>, String> {
+    let idea = get_idea(idea_id).ok_or_else(|| "idea_id not found".to_string())?;
+    ensure_owner_or_submitter(&idea)?;
+    let mut res: Vec<Doc> = Vec::new();
+    let mut bytes: usize = 0;
+    DOCS.with(|docs| {
+        let docs = docs.borrow();
+        for doc in idea.doc_ids.iter().skip(offset as usize).filter_map(|id| docs.get(id)) {
+            if !res.is_empty() && bytes + doc.data.len() > MAX_DOC_REPLY_BYTES {
+                break;
+            }
+            bytes += doc.data.len();
+            res.push(doc.clone());
+        }
+    });
+    Ok(res)
+}
+
+///return docs with idea_id
+#[query]
+fn get_doc(doc_id: u64) -> Option<Doc> {
+    DOCS.with(|docs| docs.borrow().get(&doc_id).cloned())
+}
+
+/// Whether a campaign falls under `status`. Active/Ended are decided on `end_date` alone, so a
+/// paid-out campaign is still listed as Ended: `days_left` is clamped at 0 and rounds down, so it
+/// can't tell ended from last-day campaigns. PaidOut/Refunded match the stored status.
+fn has_status(c: &Campaign, status: &CampaignStatus, now: u64) -> bool {
+    match status {
+        CampaignStatus::Active => c.end_date > now,
+        CampaignStatus::Ended => c.end_date <= now,
+        CampaignStatus::PaidOut => matches!(c.status, Some(CampaignStatus::PaidOut)),
+        CampaignStatus::Refunded => matches!(c.status, Some(CampaignStatus::Refunded)),
+    }
+}
+
+/// The most specific status of a campaign: its settlement if one was recorded, otherwise
+/// Active or Ended by deadline.
+fn campaign_status(c: &Campaign, now: u64) -> CampaignStatus {
+    match c.status {
+        Some(CampaignStatus::PaidOut) => CampaignStatus::PaidOut,
+        Some(CampaignStatus::Refunded) => CampaignStatus::Refunded,
+        _ if c.end_date > now => CampaignStatus::Active,
+        _ => CampaignStatus::Ended,
+    }
+}
+
+/// Lifecycle status of a live or archived campaign.
+#[query]
+fn get_campaign_status(campaign_id: u64) -> Option<CampaignStatus> {
+    find_campaign(campaign_id).map(|c| campaign_status(&c, now_secs()))
+}
+
+/// Return cards filtered by status, see `has_status`.
+#[query]
+fn get_campaign_cards_by_status(status: CampaignStatus) -> Vec<CampaignCard> {
+    let now = now_secs();
+    CAMPAIGNS.with(|store| {
+        store
+            .borrow()
+            .iter()
+            .filter(|(_, c)| has_status(c, &status, now))
+            .filter_map(|(_, c)| get_idea(c.idea_id).map(|idea| to_card(&c, &idea)))
+            .collect()
+    })
+}
+
+/// `get_campaign_cards_by_status`, one page at a time: Active ordered by nearest deadline,
+/// the others by most recently ended.
+#[query]
+fn get_campaign_cards_by_status_paged(status: CampaignStatus, offset: u64, limit: u64) -> CampaignCardPage {
+    let mut cards = get_campaign_cards_by_status(status.clone());
+    match status {
+        CampaignStatus::Active => cards.sort_by_key(|card| (card.end_date, card.id)),
+        CampaignStatus::Ended | CampaignStatus::PaidOut | CampaignStatus::Refunded => {
+            cards.sort_by(|a, b| b.end_date.cmp(&a.end_date).then(a.id.cmp(&b.id)))
+        }
+    }
+    let total = cards.len() as u64;
+    let items: Vec<CampaignCard> = cards
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit.min(MAX_PAGE_LIMIT) as usize)
+        .collect();
+    let next = offset.saturating_add(items.len() as u64);
+    CampaignCardPage {
+        items,
+        total,
+        next_offset: (next < total).then_some(next),
+    }
+}
+
+/// Add a campaign to the end of the featured list (owner only). It must exist and not have ended.
+#[update]
+fn feature_campaign(campaign_id: u64) -> Result<(), String> {
+    ensure_owner()?;
+    let campaign = get_campaign(campaign_id).ok_or_else(|| "Campaign not found".to_string())?;
+    if campaign.end_date <= now_secs() {
+        return Err("campaign already ended".into());
+    }
+    FEATURED.with(|f| {
+        let mut f = f.borrow_mut();
+        if f.iter().any(|(_, id)| id == campaign_id) {
+            return Err("campaign already featured".to_string());
+        }
+        let seq = f.last_key_value().map(|(k, _)| k + 1).unwrap_or(1);
+        f.insert(seq, campaign_id);
+        Ok(())
+    })
+}
+
+/// Remove a campaign from the featured list (owner only).
+#[update]
+fn unfeature_campaign(campaign_id: u64) -> Result<(), String> {
+    ensure_owner()?;
+    FEATURED.with(|f| {
+        let mut f = f.borrow_mut();
+        let seq = f
+            .iter()
+            .find(|(_, id)| *id == campaign_id)
+            .map(|(seq, _)| seq)
+            .ok_or_else(|| "campaign not featured".to_string())?;
+        f.remove(&seq);
+        Ok(())
+    })
+}
+
+/// Featured campaigns as cards, in curation order.
+#[query]
+fn get_featured_campaigns() -> Vec<CampaignCard> {
+    let ids: Vec<u64> = FEATURED.with(|f| f.borrow().iter().map(|(_, id)| id).collect());
+    ids.into_iter()
+        .filter_map(|id| {
+            let c = get_campaign(id)?;
+            get_idea(c.idea_id).map(|idea| to_card(&c, &idea))
+        })
+        .collect()
+}
+
+/// Fetch a single campaign joined with its Idea; archived campaigns are included.
+#[query]
+fn get_campaign_with_idea(campaign_id: u64) -> Option<CampaignWithIdea> {
+    let c = find_campaign(campaign_id)?;
+    get_idea(c.idea_id).map(|idea| CampaignWithIdea {
+        campaign: to_card(&c, &idea),
+        idea,
+    })
+}
+
+/// `get_campaign_with_idea` for several campaigns, in input order.
+/// Only the first `MAX_BATCH_SIZE` ids are looked up.
+#[query]
+fn get_campaign_with_idea_batch(ids: Vec<u64>) -> Vec<Option<CampaignWithIdea>> {
+    ids.into_iter().take(MAX_BATCH_SIZE).map(get_campaign_with_idea).collect()
+}
+
+/// Move a paid-out or refunded campaign out of the live listings into the archive (creator or owner only).
+/// It stays readable by id through `get_campaign_with_idea` and `get_campaign_meta`.
+#[update]
+fn archive_campaign(campaign_id: u64) -> Result<(), String> {
+    ensure_not_paused()?;
+    let campaign = get_campaign(campaign_id).ok_or_else(|| "Campaign not found".to_string())?;
+    ensure_owner_or_creator(&campaign)?;
+    if campaign.paid_out_at.is_none() && !matches!(campaign.status, Some(CampaignStatus::Refunded)) {
+        return Err("only settled campaigns can be archived".into());
+    }
+    if !fits_value_bound(&campaign) {
+        return Err("campaign record is too large to archive".into());
+    }
+    ARCHIVED_CAMPAIGNS.with(|m| m.borrow_mut().insert(campaign_id, campaign));
+    CAMPAIGNS.with(|store| store.borrow_mut().remove(&campaign_id));
+    FEATURED.with(|f| {
+        let mut f = f.borrow_mut();
+        let seqs: Vec<u64> = f.iter().filter(|(_, id)| *id == campaign_id).map(|(seq, _)| seq).collect();
+        for seq in seqs {
+            f.remove(&seq);
+        }
+    });
+    Ok(())
+}
+
+/// Page through archived campaigns as cards, ordered by campaign id.
+#[query]
+fn get_archived_campaigns(offset: u64, limit: u64) -> Vec<CampaignCard> {
+    ARCHIVED_CAMPAIGNS.with(|m| {
+        m.borrow()
+            .iter()
+            .filter_map(|(_, c)| get_idea(c.idea_id).map(|idea| to_card(&c, &idea)))
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_LIMIT) as usize)
+            .collect()
+    })
+}
+
+/// Page through campaigns joined with their Ideas, ordered by campaign id.
+/// Campaigns whose idea no longer exists are skipped.
+#[query]
+fn get_campaigns_with_ideas(offset: u64, limit: u64) -> Vec<CampaignWithIdea> {
+    CAMPAIGNS.with(|store| {
+        store
+            .borrow()
+            .iter()
+            .filter_map(|(_, c)| get_idea(c.idea_id).map(|idea| CampaignWithIdea {
+                campaign: to_card(&c, &idea),
                 idea,
             }))
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_LIMIT) as usize)
+            .collect()
     })
 }
 
@@ -340,22 +1342,222 @@ fn get_idea_by_id(idea_id: u64) -> Option<Idea> {
     get_idea(idea_id)
 }
 
+/// Total number of campaigns, for paging controls.
+#[query]
+fn get_campaigns_count() -> u64 {
+    CAMPAIGNS.with(|store| store.borrow().len())
+}
+
+/// Total number of ideas, for paging controls.
+#[query]
+fn get_ideas_count() -> u64 {
+    IDEAS.with(|map| map.borrow().len())
+}
+
+fn is_pending_review(idea: &Idea) -> bool {
+    idea.status.as_deref() == Some("pending")
+}
+
+/// Number of ideas still awaiting review (status "pending").
+#[query]
+fn get_pending_ideas_count() -> u64 {
+    IDEAS.with(|map| map.borrow().iter().filter(|(_, idea)| is_pending_review(idea)).count() as u64)
+}
+
+/// Page through ideas awaiting review as (idea_id, idea), oldest submission first.
+#[query]
+fn get_pending_ideas(offset: u64, limit: u64) -> Vec<(u64, Idea)> {
+    let mut pending: Vec<(u64, Idea)> =
+        IDEAS.with(|map| map.borrow().iter().filter(|(_, idea)| is_pending_review(idea)).collect());
+    pending.sort_by_key(|(idea_id, idea)| (idea.created_at, *idea_id));
+    pending
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit.min(MAX_PAGE_LIMIT) as usize)
+        .collect()
+}
+
+/// Number of documents attached to an idea (0 if the idea doesn't exist).
+#[query]
+fn get_idea_doc_count(idea_id: u64) -> u64 {
+    get_idea(idea_id).map(|idea| idea.doc_ids.len() as u64).unwrap_or(0)
+}
+
+/// Page through ideas as summaries, ordered by idea id, or with `by_priority` by priority
+/// (highest first) and then newest first.
+#[query]
+fn get_idea_summaries(offset: u64, limit: u64, by_priority: Option<bool>) -> Vec<IdeaSummary> {
+    let mut ideas: Vec<(u64, Idea)> = IDEAS.with(|map| map.borrow().iter().collect());
+    if by_priority.unwrap_or(false) {
+        ideas.sort_by(|(a_id, a), (b_id, b)| {
+            (b.priority.unwrap_or(0), b.created_at, b_id).cmp(&(a.priority.unwrap_or(0), a.created_at, a_id))
+        });
+    }
+    ideas
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit.min(MAX_PAGE_LIMIT) as usize)
+        .map(|(idea_id, idea)| IdeaSummary {
+            idea_id,
+            title: idea.title,
+            category: idea.category,
+            status: idea.status,
+            funding_goal: idea.funding_goal,
+            current_funding: idea.current_funding,
+            doc_count: idea.doc_ids.len() as u64,
+            priority: idea.priority.unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Every idea submitted by `p` with its live and archived campaigns as cards, ordered by idea id.
+/// Ideas created before submitters were tracked have no owner and never appear here.
+#[query]
+fn get_founder_portfolio(p: Principal) -> Vec<IdeaPortfolioEntry> {
+    let ideas: Vec<(u64, Idea)> = IDEAS.with(|map| {
+        map.borrow().iter().filter(|(_, idea)| idea.owner == Some(p)).collect()
+    });
+    ideas
+        .into_iter()
+        .map(|(idea_id, idea)| {
+            let campaigns: Vec<CampaignCard> =
+                idea_campaigns(idea_id).iter().map(|c| to_card(c, &idea)).collect();
+            let total_raised = campaigns.iter().fold(0u64, |acc, c| acc.saturating_add(c.amount_raised));
+            IdeaPortfolioEntry { idea_id, idea, campaigns, total_raised }
+        })
+        .collect()
+}
+
 // ------------- Fund_Flow Integration Methods -------------
 
 /// Get campaign metadata for Fund_Flow canister
 #[query]
 fn get_campaign_meta(campaign_id: u64) -> Option<CampaignMeta> {
-    get_campaign(campaign_id).map(|campaign| CampaignMeta {
+    find_campaign(campaign_id).map(|campaign| CampaignMeta {
         campaign_id: campaign.id,
         goal: campaign.goal,
         amount_raised: campaign.amount_raised,
         end_date_secs: campaign.end_date,
+        owner_wallet: campaign.owner_wallet,
+        currency: campaign.currency,
+        decimals: campaign.decimals,
+        tax_rate_bps: campaign.tax_rate_bps,
+        combined_max_per_backer: campaign.combined_max_per_backer,
+        creator: campaign.creator,
+        closed_at: campaign.closed_at,
     })
 }
 
+/// `get_campaign_meta` for many campaigns in one call, in request order.
+/// Only the first `MAX_BATCH_SIZE` ids are looked up.
+#[query]
+fn get_campaign_meta_batch(ids: Vec<u64>) -> Vec<Option<CampaignMeta>> {
+    ids.into_iter().take(MAX_BATCH_SIZE).map(get_campaign_meta).collect()
+}
+
+/// Set the wallet Fund_Flow pays released funds to (creator or owner only).
+/// Locked once the payout has happened.
+#[update]
+fn set_campaign_owner_wallet(campaign_id: u64, wallet: Principal) -> Result<(), String> {
+    ensure_not_paused()?;
+    let campaign = get_campaign(campaign_id).ok_or_else(|| "Campaign not found".to_string())?;
+    ensure_owner_or_creator(&campaign)?;
+    if campaign.paid_out_at.is_some() {
+        return Err("funds already released; wallet can no longer change".into());
+    }
+    update_campaign(campaign_id, |c| c.owner_wallet = Some(wallet));
+    Ok(())
+}
+
+/// Set the unit a campaign's goal is denominated in (creator or owner only), e.g. ("EGP", 2).
+/// Campaigns start in legacy units (no currency); the unit can only change before any funds are raised.
+/// The goal is not rescaled, so set it in the new unit with the same call.
+#[update]
+fn set_campaign_currency(campaign_id: u64, currency: String, decimals: u8, goal: u64) -> Result<(), String> {
+    ensure_not_paused()?;
+    let campaign = get_campaign(campaign_id).ok_or_else(|| "Campaign not found".to_string())?;
+    ensure_owner_or_creator(&campaign)?;
+    if campaign.amount_raised > 0 {
+        return Err("currency can't change after funds were raised".into());
+    }
+    let currency = currency.trim().to_uppercase();
+    if currency.is_empty() || currency.len() > 8 || !currency.chars().all(|ch| ch.is_ascii_alphanumeric()) {
+        return Err("currency must be a 1-8 character code".into());
+    }
+    if decimals > MAX_DECIMALS {
+        return Err(format!("decimals must be at most {}", MAX_DECIMALS));
+    }
+    if goal == 0 {
+        return Err("goal must be > 0".into());
+    }
+    update_campaign(campaign_id, |c| {
+        c.currency = Some(currency);
+        c.decimals = Some(decimals);
+        c.goal = goal;
+    });
+    Ok(())
+}
+
+/// Cap how much one backer may put into a campaign across ICP and fiat together, in goal units;
+/// `None` removes the cap (creator or owner only). Fund_Flow checks it on each new contribution,
+/// so lowering it never touches existing ones.
+#[update]
+fn set_campaign_backer_cap(campaign_id: u64, cap: Option<u64>) -> Result<(), String> {
+    ensure_not_paused()?;
+    let campaign = get_campaign(campaign_id).ok_or_else(|| "Campaign not found".to_string())?;
+    ensure_owner_or_creator(&campaign)?;
+    if cap == Some(0) {
+        return Err("cap must be > 0".into());
+    }
+    update_campaign(campaign_id, |c| c.combined_max_per_backer = cap);
+    Ok(())
+}
+
+/// Set the VAT rate (basis points) included in a campaign's contributions; 0 disables it
+/// (creator or owner only). Can only change before any funds are raised.
+#[update]
+fn set_campaign_tax_rate(campaign_id: u64, tax_rate_bps: u16) -> Result<(), String> {
+    ensure_not_paused()?;
+    let campaign = get_campaign(campaign_id).ok_or_else(|| "Campaign not found".to_string())?;
+    ensure_owner_or_creator(&campaign)?;
+    if campaign.amount_raised > 0 {
+        return Err("tax rate can't change after funds were raised".into());
+    }
+    if tax_rate_bps > MAX_TAX_RATE_BPS {
+        return Err(format!("tax rate must be at most {} bps", MAX_TAX_RATE_BPS));
+    }
+    update_campaign(campaign_id, |c| c.tax_rate_bps = tax_rate_bps);
+    Ok(())
+}
+
+#[query]
+fn get_campaign_owner_wallet(campaign_id: u64) -> Option<Principal> {
+    get_campaign(campaign_id).and_then(|c| c.owner_wallet)
+}
+
+/// The Fund_Flow canister trusted to send `receive_*` notifications, if configured.
+#[query]
+fn get_fund_flow_canister() -> Option<Principal> {
+    Some(FUND_FLOW.with(|c| *c.borrow().get())).filter(|p| *p != Principal::anonymous())
+}
+
+/// Register the Fund_Flow canister id (owner only). Until this is set the `receive_*`
+/// notifications are rejected, since anyone could otherwise inflate `amount_raised`.
+#[update]
+fn set_fund_flow_canister(fund_flow: Principal) -> Result<(), String> {
+    ensure_owner()?;
+    if !is_canister_principal(&fund_flow) {
+        return Err("not a canister id".into());
+    }
+    FUND_FLOW.with(|c| c.borrow_mut().set(fund_flow).expect("set fund flow"));
+    Ok(())
+}
+
 /// Receive ICP contribution from Fund_Flow canister
 #[update]
-fn receive_icp_contribution(campaign_id: u64, amount_e8s: u64) -> Result<(), String> {
+fn receive_icp_contribution(campaign_id: u64, amount_e8s: u64, backer: Principal, is_new_backer: bool) -> Result<(), String> {
+    ensure_fund_flow()?;
+    ensure_not_paused()?;
     // Verify campaign exists
     let Some(campaign) = get_campaign(campaign_id) else {
         return Err("Campaign not found".into());
@@ -364,22 +1566,21 @@ fn receive_icp_contribution(campaign_id: u64, amount_e8s: u64) -> Result<(), Str
     // Update ICP contributions tracking
     ICP_CONTRIBUTIONS.with(|contributions| {
         let mut contributions = contributions.borrow_mut();
-        let current = contributions.get(&campaign_id).unwrap_or(&0).clone();
+        let current = contributions.get(&campaign_id).unwrap_or(0);
         contributions.insert(campaign_id, current + amount_e8s);
     });
     
     // Update campaign amount raised
     let new_amount = campaign.amount_raised + amount_e8s;
     update_campaign_amount(campaign_id, new_amount);
+    if is_new_backer {
+        update_campaign(campaign_id, |c| c.backer_count += 1);
+        let count = campaign.backer_count;
+        log_admin_action("new_backer", campaign_id, count, count + 1, format!("backer {}", backer));
+    }
     
     // Update the idea's current funding as well
-    IDEAS.with(|ideas| {
-        if let Some(mut idea) = ideas.borrow_mut().get(&campaign.idea_id) {
-            idea.current_funding = idea.current_funding.saturating_add(amount_e8s);
-            idea.updated_at = ic_cdk::api::time();
-            ideas.borrow_mut().insert(campaign.idea_id, idea);
-        }
-    });
+    recompute_idea_funding(campaign.idea_id);
     
     Ok(())
 }
@@ -387,38 +1588,865 @@ fn receive_icp_contribution(campaign_id: u64, amount_e8s: u64) -> Result<(), Str
 /// Receive payout notification from Fund_Flow canister
 #[update]
 fn receive_payout(campaign_id: u64, total_amount: u64) -> Result<(), String> {
-    // This method is called when Fund_Flow releases funds to project owner
-    // For now, we just log the payout. In a real implementation, you might:
-    // - Transfer ICP to project owner's wallet
-    // - Update campaign status
-    // - Send notifications
-    
+    ensure_fund_flow()?;
+    ensure_not_paused()?;
+    // Called when Fund_Flow releases funds to the project owner; Fund_Flow sends the ICP itself
+    let Some(campaign) = get_campaign(campaign_id) else {
+        return Err("Campaign not found".into());
+    };
+    // Fund_Flow may redeliver a payout notification it queued after a transient failure
+    if campaign.paid_out_at.is_some() {
+        ic_cdk::println!("Payout for campaign {} already recorded; ignoring repeat", campaign_id);
+        return Ok(());
+    }
+
     ic_cdk::println!("Payout received for campaign {}: {} e8s", campaign_id, total_amount);
-    
-    // Update campaign status or mark as completed
-    // You could add a status field to Campaign struct for this
-    
+
+    // Mark as paid out; this also locks the payout wallet
+    let now = now_secs();
+    update_campaign(campaign_id, |c| {
+        c.paid_out_at = Some(now);
+        c.status = Some(CampaignStatus::PaidOut);
+    });
+
+    Ok(())
+}
+
+/// Receive refund notification from Fund_Flow canister: the campaign's backers got their money
+/// back, so it no longer counts toward its idea's funding. Repeats are ignored.
+#[update]
+fn receive_refund(campaign_id: u64, total_amount: u64) -> Result<(), String> {
+    ensure_fund_flow()?;
+    ensure_not_paused()?;
+    let Some(campaign) = get_campaign(campaign_id) else {
+        return Err("Campaign not found".into());
+    };
+    if matches!(campaign.status, Some(CampaignStatus::Refunded)) {
+        return Ok(());
+    }
+    if campaign.paid_out_at.is_some() {
+        return Err("campaign was already paid out".into());
+    }
+    ic_cdk::println!("Refund recorded for campaign {}: {}", campaign_id, total_amount);
+    update_campaign(campaign_id, |c| c.status = Some(CampaignStatus::Refunded));
+    recompute_idea_funding(campaign.idea_id);
+    Ok(())
+}
+
+/// Receive notification from Fund_Flow that one ICP contribution left a campaign (rolled over to
+/// another one), so it stops counting toward `amount_raised` and the ICP share. Unlike
+/// `receive_refund` the campaign itself is untouched. `backer_count` is kept as it is: the
+/// backer may still have other contributions in the campaign.
+#[update]
+fn receive_icp_refund(campaign_id: u64, amount_e8s: u64) -> Result<(), String> {
+    ensure_fund_flow()?;
+    ensure_not_paused()?;
+    let Some(campaign) = get_campaign(campaign_id) else {
+        return Err("Campaign not found".into());
+    };
+    if campaign.paid_out_at.is_some() {
+        return Err("campaign was already paid out".into());
+    }
+    ICP_CONTRIBUTIONS.with(|contributions| {
+        let mut contributions = contributions.borrow_mut();
+        let current = contributions.get(&campaign_id).unwrap_or(0);
+        contributions.insert(campaign_id, current.saturating_sub(amount_e8s));
+    });
+    update_campaign_amount(campaign_id, campaign.amount_raised.saturating_sub(amount_e8s));
+    recompute_idea_funding(campaign.idea_id);
+    Ok(())
+}
+
+/// Manually correct a campaign's `amount_raised` (owner only), e.g. after a reversed off-chain payment.
+/// The linked idea's `current_funding` is recomputed and the change is audited.
+#[update]
+fn adjust_campaign_amount(campaign_id: u64, new_amount: u64, reason: String) -> Result<(), String> {
+    ensure_owner()?;
+    let Some(campaign) = get_campaign(campaign_id) else {
+        return Err("Campaign not found".into());
+    };
+    let reason = reason.trim().to_string();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_LEN {
+        return Err(format!("reason must be 1-{} characters", MAX_REASON_LEN));
+    }
+    let old_amount = campaign.amount_raised;
+    if new_amount == old_amount {
+        return Err("new_amount equals the current amount_raised".into());
+    }
+
+    update_campaign_amount(campaign_id, new_amount);
+    recompute_idea_funding(campaign.idea_id);
+    log_admin_action("adjust_campaign_amount", campaign_id, old_amount, new_amount, reason);
     Ok(())
 }
 
+/// Audit trail of owner corrections and backer count changes, oldest first.
+#[query]
+fn get_admin_actions(offset: u64, limit: u64) -> Vec<AdminAction> {
+    ADMIN_ACTIONS.with(|log| {
+        log.borrow()
+            .iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_LIMIT) as usize)
+            .map(|(_, a)| a)
+            .collect()
+    })
+}
+
 /// Get ICP contribution amount for a campaign
 #[query]
 fn get_icp_contribution(campaign_id: u64) -> u64 {
     ICP_CONTRIBUTIONS.with(|contributions| {
-        contributions.borrow().get(&campaign_id).unwrap_or(&0).clone()
+        contributions.borrow().get(&campaign_id).unwrap_or(0)
     })
 }
 
-/// Get total funding (ICP + other methods) for a campaign
+/// Split a campaign's `amount_raised` into ICP and fiat. ICP contributions are already part of
+/// `amount_raised`, so the total is `amount_raised` itself and fiat is the remainder.
+#[query]
+fn get_campaign_funding_breakdown(campaign_id: u64) -> FundingBreakdown {
+    let total = get_campaign(campaign_id).map(|c| c.amount_raised).unwrap_or(0);
+    let icp_e8s = get_icp_contribution(campaign_id).min(total);
+    FundingBreakdown {
+        icp_e8s,
+        fiat_amount: total - icp_e8s,
+        total,
+    }
+}
+
+/// Get total funding (ICP + other methods) for a campaign. `amount_raised` is the single source
+/// of truth: ICP contributions are added to it on receipt, and `ICP_CONTRIBUTIONS` only tracks
+/// the ICP share of it for `get_campaign_funding_breakdown`.
 #[query]
 fn get_campaign_total_funding(campaign_id: u64) -> u64 {
-    let campaign_amount = get_campaign(campaign_id).map(|c| c.amount_raised).unwrap_or(0);
-    let icp_amount = get_icp_contribution(campaign_id);
-    campaign_amount + icp_amount
+    get_campaign(campaign_id).map(|c| c.amount_raised).unwrap_or(0)
+}
+
+
+
+
+/// Compare Fund_Flow's held + released total for a campaign with `amount_raised` here
+/// (owner only). A non-zero delta points at double counting or a lost notification.
+#[update]
+async fn verify_campaign_consistency(fund_flow: Principal, campaign_id: u64) -> Result<ConsistencyReport, String> {
+    ensure_owner()?;
+    let campaign = get_campaign(campaign_id).ok_or_else(|| "Campaign not found".to_string())?;
+    let (summary,): (FundFlowEscrowSummary,) = ic_cdk::call(fund_flow, "get_escrow_summary", (campaign_id,))
+        .await
+        .map_err(|(code, msg)| format!("Fund_Flow call failed: {:?} {}", code, msg))?;
+
+    let fund_flow_total = summary.total_held.saturating_add(summary.total_released);
+    let delta = (campaign.amount_raised as i128 - fund_flow_total as i128)
+        .clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+    Ok(ConsistencyReport {
+        campaign_id,
+        fund_flow_total,
+        backend_amount_raised: campaign.amount_raised,
+        backend_icp_recorded: get_icp_contribution(campaign_id),
+        delta,
+        consistent: delta == 0,
+    })
 }
 
+/// Fund_Flow's escrow summaries for `ids` (`get_escrow_summary_batch`), in the same order.
+#[cfg(not(test))]
+async fn fetch_escrow_summaries(fund_flow: Principal, ids: Vec<u64>) -> Result<Vec<FundFlowEscrowSummary>, String> {
+    let (summaries,): (Vec<FundFlowEscrowSummary>,) = ic_cdk::call(fund_flow, "get_escrow_summary_batch", (ids,))
+        .await
+        .map_err(|(code, msg)| format!("Fund_Flow call failed: {:?} {}", code, msg))?;
+    Ok(summaries)
+}
+#[cfg(test)]
+async fn fetch_escrow_summaries(_fund_flow: Principal, ids: Vec<u64>) -> Result<Vec<FundFlowEscrowSummary>, String> {
+    Ok(ids.into_iter().map(mock::escrow_summary).collect())
+}
 
+/// Ended campaigns that still have funds held in `fund_flow`'s escrow, i.e. were never released
+/// or refunded (owner only). Costs one inter-canister call: only the `MAX_BATCH_SIZE` oldest
+/// ended, unpaid campaigns are checked, so re-run after settling some to see the next ones.
+#[update]
+async fn get_unsettled_campaigns(fund_flow: Principal) -> Result<Vec<u64>, String> {
+    ensure_owner()?;
+    let now = now_secs();
+    let ended: Vec<u64> = CAMPAIGNS.with(|store| {
+        let mut ids: Vec<u64> = store
+            .borrow()
+            .iter()
+            .filter(|(_, c)| c.end_date <= now && c.paid_out_at.is_none())
+            .map(|(id, _)| id)
+            .collect();
+        ids.sort_unstable();
+        ids.truncate(MAX_BATCH_SIZE);
+        ids
+    });
+    if ended.is_empty() {
+        return Ok(Vec::new());
+    }
+    let summaries = fetch_escrow_summaries(fund_flow, ended).await?;
+    Ok(summaries
+        .into_iter()
+        .filter(|s| s.total_held > 0)
+        .map(|s| s.campaign_id)
+        .collect())
+}
 
+// ------------- Maintenance -------------
+
+/// Halt (or resume) all non-owner state-changing calls (owner only).
+/// Owner-only endpoints keep working so the owner can recover while paused.
+#[update]
+fn set_paused(paused: bool) -> Result<(), String> {
+    ensure_owner()?;
+    PAUSED.with(|p| p.borrow_mut().set(paused).expect("set paused"));
+    Ok(())
+}
+
+#[query]
+fn is_paused() -> bool {
+    PAUSED.with(|p| *p.borrow().get())
+}
+
+/// Lets the frontend check it was built against this interface.
+#[query]
+fn get_version() -> VersionInfo {
+    let hash = CANDID_SCHEMA.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    VersionInfo {
+        version: CANISTER_VERSION.to_string(),
+        candid_schema_hash: format!("{:016x}", hash),
+        build_time: option_env!("BUILD_TIME").map(str::to_string),
+    }
+}
+
+// ------------- Lifecycle -------------
+
+// ---------- Schema versioning ----------
+//
+// Stored records are candid, which still decodes older blobs as long as every field added since
+// is an `Option` (missing => None), so adding optional fields needs no migration. Anything that
+// breaks that (renames, type changes, new required fields) bumps `SCHEMA_VERSION` and adds a
+// step to `migrate_schema` that rewrites the affected maps from the previous version. The version
+// the data was written with lives in `STORED_SCHEMA_VERSION`; 0 means it predates versioning.
+
+/// Refuse data written by a newer schema; an older wasm would misread or drop what it added.
+fn check_schema_version(stored: u32) -> Result<(), String> {
+    if stored > SCHEMA_VERSION {
+        return Err(format!("stable data uses schema v{}, newer than v{}; refusing to downgrade", stored, SCHEMA_VERSION));
+    }
+    Ok(())
+}
+
+/// Bring stable data up to `SCHEMA_VERSION`, one step at a time. Refuses (trapping, which rolls
+/// the upgrade back) to run against data written by a newer schema.
+fn migrate_schema() {
+    let stored = STORED_SCHEMA_VERSION.with(|c| *c.borrow().get());
+    if let Err(e) = check_schema_version(stored) {
+        ic_cdk::trap(&e);
+    }
+    for from in stored..SCHEMA_VERSION {
+        match from {
+            // v0 -> v1: versioning introduced; every field added so far is optional
+            0 => {}
+            _ => unreachable!("no migration from schema v{}", from),
+        }
+        ic_cdk::println!("migrated stable schema v{} -> v{}", from, from + 1);
+    }
+    STORED_SCHEMA_VERSION.with(|c| c.borrow_mut().set(SCHEMA_VERSION).expect("set schema version"));
+}
+
+/// Drop ingress messages to the canister-to-canister notifications before they cost cycles.
+/// Only ingress passes through here, so inter-canister calls from Fund_Flow are unaffected.
+#[inspect_message]
+fn inspect_message() {
+    match ic_cdk::api::call::method_name().as_str() {
+        "receive_icp_contribution" | "receive_payout" | "receive_refund" | "receive_icp_refund" => {}
+        _ => ic_cdk::api::call::accept_message(),
+    }
+}
+
+#[init]
+fn init() {
+    // The installer becomes the owner
+    OWNER.with(|o| o.borrow_mut().set(caller()).expect("set owner"));
+    STORED_SCHEMA_VERSION.with(|c| c.borrow_mut().set(SCHEMA_VERSION).expect("set schema version"));
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    migrate_schema();
+    seed_owner_on_upgrade();
+}
 
 // Export Candid for tooling & UI integration
 ic_cdk::export_candid!();
+
+/// Stand-ins for the system API in unit tests.
+#[cfg(test)]
+mod mock {
+    use super::*;
+    use std::cell::Cell;
+
+    thread_local! {
+        pub static NOW_NS: Cell<u64> = const { Cell::new(0) };
+        pub static CALLER: Cell<Principal> = const { Cell::new(Principal::anonymous()) };
+        // Fund_Flow's held escrow by campaign id; campaigns not listed hold nothing
+        pub static HELD: RefCell<HashMap<u64, u64>> = RefCell::new(HashMap::new());
+    }
+
+    pub fn escrow_summary(campaign_id: u64) -> FundFlowEscrowSummary {
+        let total_held = HELD.with(|h| h.borrow().get(&campaign_id).copied().unwrap_or(0));
+        FundFlowEscrowSummary { campaign_id, total_held, total_released: 0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    /// Drive an endpoint whose awaits all resolve immediately against `mock`.
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = std::pin::pin!(f);
+        match f.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(out) => out,
+            Poll::Pending => panic!("future is waiting on a real inter-canister call"),
+        }
+    }
+
+    fn user(n: u8) -> Principal {
+        Principal::from_slice(&[n; 29])
+    }
+
+    fn as_caller(p: Principal) {
+        mock::CALLER.with(|c| c.set(p));
+    }
+
+    fn as_owner(p: Principal) {
+        OWNER.with(|o| o.borrow_mut().set(p).unwrap());
+        as_caller(p);
+    }
+
+    const WEEK_SECS: u64 = 7 * 24 * 60 * 60;
+
+    fn new_idea() -> u64 {
+        submit_idea("Solar kiosk", &"d".repeat(DEFAULT_MIN_DESCRIPTION_LEN as usize)).unwrap()
+    }
+
+    const FUND_FLOW_ID: Principal = Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 2, 1, 1]);
+
+    /// Register `FUND_FLOW_ID` as the Fund_Flow canister (owner `user(1)`).
+    fn configure_fund_flow() {
+        as_owner(user(1));
+        set_fund_flow_canister(FUND_FLOW_ID).unwrap();
+    }
+
+    fn set_now_secs(secs: u64) {
+        mock::NOW_NS.with(|n| n.set(secs * 1_000_000_000));
+    }
+
+    fn submit_idea(title: &str, description: &str) -> Result<u64, String> {
+        submit_idea_with_goal(title, description, DEFAULT_MIN_FUNDING_GOAL)
+    }
+
+    fn submit_idea_with_goal(title: &str, description: &str, funding_goal: u64) -> Result<u64, String> {
+        create_idea(
+            title.into(),
+            description.into(),
+            funding_goal,
+            "Acme Ltd".into(),
+            "founder@example.com".into(),
+            "tech".into(),
+            1,
+        )
+    }
+
+    #[test]
+    fn upgrade_seeds_a_missing_owner_but_keeps_an_existing_one() {
+        as_caller(user(1));
+        post_upgrade();
+        assert_eq!(owner(), user(1));
+        assert!(ensure_owner().is_ok());
+
+        as_caller(user(2));
+        post_upgrade();
+        assert_eq!(owner(), user(1));
+        assert!(ensure_owner().is_err());
+    }
+
+    #[test]
+    fn only_the_configured_fund_flow_canister_passes_the_gate() {
+        let fund_flow = FUND_FLOW_ID;
+        let other_canister = Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 3, 1, 1]);
+        as_caller(fund_flow);
+        assert!(ensure_fund_flow().is_err(), "unconfigured gate must reject");
+
+        as_owner(user(1));
+        assert!(set_fund_flow_canister(user(2)).is_err(), "user principals are not canisters");
+        set_fund_flow_canister(fund_flow).unwrap();
+        assert!(ensure_fund_flow().is_err());
+
+        as_caller(other_canister);
+        assert!(ensure_fund_flow().is_err());
+        as_caller(fund_flow);
+        assert!(ensure_fund_flow().is_ok());
+    }
+
+    #[test]
+    fn idea_text_minimums_apply_at_the_boundary_and_are_owner_settable() {
+        let description = "d".repeat(DEFAULT_MIN_DESCRIPTION_LEN as usize);
+        assert!(submit_idea("ab", &description).is_err());
+        assert!(submit_idea("abc", &description[1..]).is_err());
+        assert!(submit_idea("abc", &description).is_ok());
+
+        as_owner(user(1));
+        set_idea_text_minimums(5, 10).unwrap();
+        assert!(submit_idea("abcd", &description).is_err());
+        assert!(submit_idea("abcde", &"d".repeat(10)).is_ok());
+
+        as_caller(user(2));
+        assert!(set_idea_text_minimums(0, 0).is_err());
+    }
+
+    #[test]
+    fn uploads_stop_at_the_per_idea_limit_without_consuming_ids() {
+        let idea_id = submit_idea("abc", &"d".repeat(DEFAULT_MIN_DESCRIPTION_LEN as usize)).unwrap();
+        for i in 1..=MAX_DOCS_PER_IDEA as u64 {
+            assert_eq!(upload_doc(idea_id, format!("doc{}", i), "text/plain".into(), vec![1], 0), Ok(i));
+        }
+        assert!(upload_doc(idea_id, "extra".into(), "text/plain".into(), vec![1], 0).is_err());
+        assert!(upload_doc(idea_id + 1, "orphan".into(), "text/plain".into(), vec![1], 0).is_err());
+        assert_eq!(DOC_COUNTER.with(|c| *c.borrow()), MAX_DOCS_PER_IDEA as u64);
+        assert_eq!(get_idea(idea_id).unwrap().doc_ids.len(), MAX_DOCS_PER_IDEA);
+    }
+
+    #[test]
+    fn state_changes_are_blocked_while_paused() {
+        let description = "d".repeat(DEFAULT_MIN_DESCRIPTION_LEN as usize);
+        as_owner(user(1));
+        set_paused(true).unwrap();
+        post_upgrade();
+        assert!(is_paused());
+
+        as_caller(user(2));
+        assert_eq!(submit_idea("abc", &description), Err("canister paused".to_string()));
+        assert!(set_paused(false).is_err());
+
+        as_caller(user(1));
+        set_paused(false).unwrap();
+        as_caller(user(2));
+        assert!(submit_idea("abc", &description).is_ok());
+    }
+
+    #[test]
+    fn an_idea_gets_one_active_campaign_unless_the_owner_raises_the_cap() {
+        let idea_id = new_idea();
+        as_caller(user(3));
+        let first = create_campaign_with_duration(idea_id, 1_000, WEEK_SECS).unwrap();
+        assert_eq!(
+            create_campaign_with_duration(idea_id, 2_000, WEEK_SECS),
+            Err("idea already has an active campaign".to_string())
+        );
+
+        as_owner(user(1));
+        set_max_active_campaigns_per_idea(2).unwrap();
+        as_caller(user(3));
+        let second = create_campaign_with_duration(idea_id, 2_000, WEEK_SECS).unwrap();
+        assert_ne!(first, second);
+
+        // an ended campaign no longer counts
+        set_now_secs(WEEK_SECS + 1);
+        assert!(create_campaign_with_duration(idea_id, 3_000, WEEK_SECS).is_ok());
+    }
+
+    #[test]
+    fn funding_breakdown_splits_icp_from_fiat() {
+        configure_fund_flow();
+        let idea_id = new_idea();
+        let split = |id| {
+            let b = get_campaign_funding_breakdown(id);
+            (b.icp_e8s, b.fiat_amount, b.total)
+        };
+        set_max_active_campaigns_per_idea(3).unwrap();
+        let icp_only = create_campaign_with_duration(idea_id, 1_000, WEEK_SECS).unwrap();
+        let fiat_only = create_campaign_with_duration(idea_id, 2_000, WEEK_SECS).unwrap();
+        let mixed = create_campaign_with_duration(idea_id, 3_000, WEEK_SECS).unwrap();
+
+        as_caller(FUND_FLOW_ID);
+        receive_icp_contribution(icp_only, 300, user(2), true).unwrap();
+        receive_icp_contribution(mixed, 200, user(2), true).unwrap();
+        as_owner(user(1));
+        adjust_campaign_amount(fiat_only, 500, "bank transfers".into()).unwrap();
+        adjust_campaign_amount(mixed, 700, "bank transfers".into()).unwrap();
+
+        assert_eq!(split(icp_only), (300, 0, 300));
+        assert_eq!(split(fiat_only), (0, 500, 500));
+        assert_eq!(split(mixed), (200, 500, 700));
+    }
+
+    #[test]
+    fn unsettled_campaigns_are_the_ended_ones_with_escrow_left() {
+        configure_fund_flow();
+        set_max_active_campaigns_per_idea(3).unwrap();
+        let idea_id = new_idea();
+        let settled = create_campaign_with_duration(idea_id, 1_000, WEEK_SECS).unwrap();
+        let stuck = create_campaign_with_duration(idea_id, 2_000, WEEK_SECS).unwrap();
+        let running = create_campaign_with_duration(idea_id, 3_000, 2 * WEEK_SECS).unwrap();
+        mock::HELD.with(|h| h.borrow_mut().extend([(stuck, 500), (running, 500)]));
+        assert_eq!(block_on(get_unsettled_campaigns(FUND_FLOW_ID)), Ok(vec![]));
+
+        set_now_secs(WEEK_SECS);
+        assert_eq!(block_on(get_unsettled_campaigns(FUND_FLOW_ID)), Ok(vec![stuck]), "{} holds nothing", settled);
+        as_caller(user(2));
+        assert!(block_on(get_unsettled_campaigns(FUND_FLOW_ID)).is_err(), "owner only");
+    }
+
+    #[test]
+    fn funding_goals_must_fall_within_the_configured_bounds() {
+        let description = "d".repeat(DEFAULT_MIN_DESCRIPTION_LEN as usize);
+        let goal = |g| submit_idea_with_goal("abc", &description, g);
+        assert_eq!(goal(0), Err("funding_goal must be > 0".to_string()));
+        assert!(goal(DEFAULT_MIN_FUNDING_GOAL - 1).is_err());
+        assert!(goal(DEFAULT_MAX_FUNDING_GOAL + 1).is_err());
+        assert!(goal(u64::MAX).is_err());
+        assert!(goal(DEFAULT_MIN_FUNDING_GOAL * 100).is_ok());
+
+        as_owner(user(1));
+        assert!(set_funding_goal_bounds(0, 10).is_err());
+        assert!(set_funding_goal_bounds(10, 9).is_err());
+        set_funding_goal_bounds(10, 20).unwrap();
+        assert_eq!(get_funding_goal_bounds(), (10, 20));
+        assert!(goal(9).is_err());
+        assert!(goal(21).is_err());
+        assert!(goal(15).is_ok());
+    }
+
+    /// `Idea` as the first release stored it, before `owner` and `priority`.
+    #[derive(CandidType)]
+    struct IdeaV1 {
+        title: String,
+        description: String,
+        funding_goal: u64,
+        current_funding: u64,
+        legal_entity: String,
+        status: Option<String>,
+        contact_info: String,
+        category: String,
+        business_registration: u8,
+        created_at: u64,
+        updated_at: u64,
+        doc_ids: Vec<u64>,
+    }
+
+    #[test]
+    fn a_v1_idea_blob_decodes_into_the_current_struct_after_migration() {
+        let v1 = IdeaV1 {
+            title: "Solar kiosk".into(),
+            description: "Prepaid solar charging".into(),
+            funding_goal: 5_000,
+            current_funding: 0,
+            legal_entity: "Acme Ltd".into(),
+            status: Some("approved".into()),
+            contact_info: "founder@example.com".into(),
+            category: "energy".into(),
+            business_registration: 1,
+            created_at: 1,
+            updated_at: 2,
+            doc_ids: vec![4],
+        };
+        let idea = Idea::from_bytes(Cow::Owned(Encode!(&v1).unwrap()));
+        assert_eq!((idea.title.as_str(), idea.funding_goal, idea.doc_ids), ("Solar kiosk", 5_000, vec![4]));
+        assert_eq!((idea.owner, idea.priority), (None, None));
+
+        as_caller(user(1));
+        post_upgrade();
+        assert_eq!(STORED_SCHEMA_VERSION.with(|c| *c.borrow().get()), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn a_refunded_campaign_stops_counting_toward_its_idea() {
+        configure_fund_flow();
+        let idea_id = new_idea();
+        set_max_active_campaigns_per_idea(2).unwrap();
+        let live = create_campaign_with_duration(idea_id, 1_000, WEEK_SECS).unwrap();
+        let refunded = create_campaign_with_duration(idea_id, 2_000, WEEK_SECS).unwrap();
+
+        as_caller(FUND_FLOW_ID);
+        receive_icp_contribution(live, 300, user(2), true).unwrap();
+        receive_icp_contribution(refunded, 500, user(3), true).unwrap();
+        assert_eq!(get_idea(idea_id).unwrap().current_funding, 800);
+
+        receive_refund(refunded, 500).unwrap();
+        receive_refund(refunded, 500).unwrap();
+        assert_eq!(get_idea(idea_id).unwrap().current_funding, 300);
+        assert!(matches!(get_campaign(refunded).unwrap().status, Some(CampaignStatus::Refunded)));
+
+        as_caller(user(2));
+        assert!(receive_refund(live, 300).is_err(), "only Fund_Flow reports refunds");
+    }
+
+    #[test]
+    fn archived_campaigns_keep_counting_toward_funding_and_the_portfolio() {
+        configure_fund_flow();
+        let idea_id = new_idea();
+        set_max_active_campaigns_per_idea(3).unwrap();
+        let paid = create_campaign_with_duration(idea_id, 1_000, WEEK_SECS).unwrap();
+        let refunded = create_campaign_with_duration(idea_id, 2_000, WEEK_SECS).unwrap();
+        let live = create_campaign_with_duration(idea_id, 3_000, WEEK_SECS).unwrap();
+
+        as_caller(FUND_FLOW_ID);
+        receive_icp_contribution(paid, 300, user(2), true).unwrap();
+        receive_icp_contribution(refunded, 500, user(3), true).unwrap();
+        as_owner(user(1));
+        assert!(archive_campaign(live).is_err(), "an unsettled campaign stays live");
+
+        as_caller(FUND_FLOW_ID);
+        receive_payout(paid, 300).unwrap();
+        receive_refund(refunded, 500).unwrap();
+        as_caller(user(1));
+        archive_campaign(paid).unwrap();
+        archive_campaign(refunded).unwrap();
+
+        as_caller(FUND_FLOW_ID);
+        receive_icp_contribution(live, 200, user(2), false).unwrap();
+        assert_eq!(get_idea(idea_id).unwrap().current_funding, 500);
+
+        let portfolio = get_founder_portfolio(user(1));
+        let ids: Vec<u64> = portfolio[0].campaigns.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![paid, refunded, live]);
+        assert_eq!(portfolio[0].total_raised, 1_000);
+    }
+
+    #[test]
+    fn a_repeated_create_campaign_returns_the_same_id_but_is_still_validated() {
+        configure_fund_flow();
+        set_now_secs(1_000);
+        let idea_id = new_idea();
+        set_max_active_campaigns_per_idea(3).unwrap();
+        let end = 1_000 + WEEK_SECS;
+        let slug = || Some("solar-kiosk".to_string());
+        let first = create_campaign(idea_id, 1_000, end, Vec::new(), slug()).unwrap();
+
+        assert_eq!(create_campaign(idea_id, 1_000, end, Vec::new(), slug()), Ok(first));
+        assert!(create_campaign(idea_id, 1_000, end, Vec::new(), Some("-bad-".into())).is_err());
+        assert!(create_campaign(idea_id, 1_000, end, vec!["x".repeat(1_000)], None).is_err());
+        as_caller(user(2));
+        assert_ne!(create_campaign(idea_id, 1_000, end, Vec::new(), None).unwrap(), first, "per caller");
+
+        as_caller(user(1));
+        set_now_secs(1_000 + DUPLICATE_CAMPAIGN_WINDOW_SECS + 1);
+        let later = create_campaign(idea_id, 1_000, end, Vec::new(), None).unwrap();
+        assert_ne!(later, first, "outside the window it is a new campaign");
+    }
+
+    #[test]
+    fn new_backers_are_counted_and_audited() {
+        configure_fund_flow();
+        let idea_id = new_idea();
+        let id = create_campaign_with_duration(idea_id, 1_000, WEEK_SECS).unwrap();
+        as_caller(FUND_FLOW_ID);
+        receive_icp_contribution(id, 100, user(2), true).unwrap();
+        receive_icp_contribution(id, 100, user(2), false).unwrap();
+        receive_icp_contribution(id, 100, user(3), true).unwrap();
+
+        assert_eq!(get_campaign(id).unwrap().backer_count, 2);
+        let log: Vec<(String, u64, u64)> =
+            get_admin_actions(0, 10).into_iter().map(|a| (a.action, a.old_value, a.new_value)).collect();
+        assert_eq!(log, vec![("new_backer".to_string(), 0, 1), ("new_backer".to_string(), 1, 2)]);
+    }
+
+    #[test]
+    fn closing_early_ends_the_campaign_now_and_tells_fund_flow() {
+        configure_fund_flow();
+        set_now_secs(1_000);
+        let idea_id = new_idea();
+        let id = create_campaign_with_duration(idea_id, 1_000, WEEK_SECS).unwrap();
+        assert_eq!(get_campaign_meta(id).unwrap().closed_at, None);
+
+        set_now_secs(2_000);
+        as_caller(user(2));
+        assert!(close_campaign_early(id).is_err(), "creator or owner only");
+        as_caller(user(1));
+        close_campaign_early(id).unwrap();
+        let meta = get_campaign_meta(id).unwrap();
+        assert_eq!((meta.end_date_secs, meta.closed_at), (2_000, Some(2_000)));
+        assert!(close_campaign_early(id).is_err(), "already ended");
+    }
+
+    #[test]
+    fn an_idea_is_accepted_up_to_the_stable_size_bound_and_refused_past_it() {
+        as_owner(user(1));
+        let mut len = DEFAULT_MIN_DESCRIPTION_LEN as usize;
+        let last_ok = loop {
+            match submit_idea("Solar kiosk", &"d".repeat(len)) {
+                Ok(_) => len += 1,
+                Err(e) => {
+                    assert!(e.contains("too large"), "{}", e);
+                    break len - 1;
+                }
+            }
+            assert!(len <= MAX_VALUE_SIZE as usize, "the bound was never hit");
+        };
+        let id = submit_idea("Solar kiosk", &"d".repeat(last_ok)).unwrap();
+        let stored = Encode!(&get_idea(id).unwrap()).unwrap().len();
+        assert!((MAX_VALUE_SIZE as usize - 1..=MAX_VALUE_SIZE as usize).contains(&stored), "{}", stored);
+    }
+
+    #[test]
+    fn data_from_a_newer_schema_is_refused_on_upgrade() {
+        assert_eq!(check_schema_version(SCHEMA_VERSION), Ok(()));
+        assert_eq!(check_schema_version(0), Ok(()));
+        assert_eq!(
+            check_schema_version(SCHEMA_VERSION + 1),
+            Err(format!("stable data uses schema v{}, newer than v{}; refusing to downgrade", SCHEMA_VERSION + 1, SCHEMA_VERSION))
+        );
+        STORED_SCHEMA_VERSION.with(|c| c.borrow_mut().set(SCHEMA_VERSION + 1).unwrap());
+        assert!(std::panic::catch_unwind(migrate_schema).is_err());
+        assert_eq!(STORED_SCHEMA_VERSION.with(|c| *c.borrow().get()), SCHEMA_VERSION + 1, "left untouched");
+    }
+
+    #[test]
+    fn slugs_are_normalized_validated_and_unique() {
+        assert_eq!(normalize_slug("  Solar-Kiosk-2 ".into()), Ok("solar-kiosk-2".to_string()));
+        for bad in ["-solar", "solar-", "solar--kiosk", "solar kiosk", "solär", "ab", &"a".repeat(MAX_SLUG_LEN + 1)] {
+            assert!(normalize_slug(bad.to_string()).is_err(), "{:?}", bad);
+        }
+        assert!(normalize_slug("a".repeat(MIN_SLUG_LEN)).is_ok());
+        assert!(normalize_slug("a".repeat(MAX_SLUG_LEN)).is_ok());
+
+        configure_fund_flow();
+        let idea_id = new_idea();
+        set_max_active_campaigns_per_idea(2).unwrap();
+        let end = now_secs() + WEEK_SECS;
+        let id = create_campaign(idea_id, 1_000, end, Vec::new(), Some("Solar-Kiosk".into())).unwrap();
+        assert_eq!(get_campaign_by_slug("SOLAR-kiosk".into()).map(|c| c.id), Some(id));
+        assert_eq!(
+            create_campaign(idea_id, 2_000, end, Vec::new(), Some("solar-kiosk".into())),
+            Err("slug already taken".to_string())
+        );
+        assert!(get_campaign_by_slug("missing".into()).is_none());
+    }
+
+    #[test]
+    fn higher_priority_ideas_sort_first_then_newest() {
+        as_owner(user(1));
+        let mut ids = Vec::new();
+        for (secs, title) in [(1, "Solar kiosk"), (2, "Water pump"), (3, "Bike rental"), (4, "Seed bank")] {
+            set_now_secs(secs);
+            ids.push(submit_idea(title, &"d".repeat(DEFAULT_MIN_DESCRIPTION_LEN as usize)).unwrap());
+        }
+        let [plain, older_boost, newer_boost, demoted] = ids[..] else { unreachable!() };
+        set_idea_priority(older_boost, 5).unwrap();
+        set_idea_priority(newer_boost, 5).unwrap();
+        set_idea_priority(demoted, -1).unwrap();
+        as_caller(user(2));
+        assert!(set_idea_priority(plain, 9).is_err(), "owner only");
+
+        let order = |by_priority| -> Vec<u64> {
+            get_idea_summaries(0, 10, by_priority).into_iter().map(|s| s.idea_id).collect()
+        };
+        assert_eq!(order(Some(true)), vec![newer_boost, older_boost, plain, demoted]);
+        assert_eq!(order(None), vec![plain, older_boost, newer_boost, demoted]);
+        assert_eq!(get_idea_by_id(older_boost).unwrap().priority, Some(5));
+    }
+
+    #[test]
+    fn an_icp_contribution_is_counted_once_in_the_total() {
+        configure_fund_flow();
+        let idea_id = new_idea();
+        let id = create_campaign_with_duration(idea_id, 1_000, WEEK_SECS).unwrap();
+        as_caller(FUND_FLOW_ID);
+        receive_icp_contribution(id, 100, user(2), true).unwrap();
+        assert_eq!(get_campaign_total_funding(id), 100);
+        assert_eq!(get_icp_contribution(id), 100);
+        assert_eq!(get_idea(idea_id).unwrap().current_funding, 100);
+    }
+
+    #[test]
+    fn a_campaign_moves_from_active_to_ended_to_paid_out() {
+        configure_fund_flow();
+        set_now_secs(1_000);
+        let idea_id = new_idea();
+        let id = create_campaign_with_duration(idea_id, 1_000, WEEK_SECS).unwrap();
+        let status = || get_campaign_status(id);
+        assert!(matches!(status(), Some(CampaignStatus::Active)));
+        assert!(matches!(get_campaign(id).unwrap().status, Some(CampaignStatus::Active)), "stored at creation");
+
+        set_now_secs(1_000 + WEEK_SECS);
+        assert!(matches!(status(), Some(CampaignStatus::Ended)));
+        as_caller(FUND_FLOW_ID);
+        receive_payout(id, 1_000).unwrap();
+        assert!(matches!(status(), Some(CampaignStatus::PaidOut)));
+        assert!(get_campaign(id).unwrap().paid_out_at.is_some());
+
+        as_caller(user(1));
+        archive_campaign(id).unwrap();
+        assert!(matches!(status(), Some(CampaignStatus::PaidOut)), "archived campaigns keep their status");
+        assert!(get_campaign_status(id + 1).is_none());
+    }
+
+    #[test]
+    fn campaigns_survive_an_upgrade() {
+        configure_fund_flow();
+        let idea_id = new_idea();
+        set_max_active_campaigns_per_idea(2).unwrap();
+        let id = create_campaign_with_duration(idea_id, 1_000, WEEK_SECS).unwrap();
+        as_caller(FUND_FLOW_ID);
+        receive_icp_contribution(id, 250, user(2), true).unwrap();
+
+        // an upgrade drops the heap and reopens every map from its stable memory
+        RECENT_CAMPAIGN_CREATIONS.with(|m| m.borrow_mut().clear());
+        let memory = MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(16)));
+        CAMPAIGNS.with(|m| *m.borrow_mut() = StableBTreeMap::init(memory));
+        let memory = MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(18)));
+        ICP_CONTRIBUTIONS.with(|m| *m.borrow_mut() = StableBTreeMap::init(memory));
+        post_upgrade();
+
+        let c = get_campaign(id).unwrap();
+        assert_eq!((c.idea_id, c.goal, c.amount_raised, c.backer_count), (idea_id, 1_000, 250, 1));
+        let breakdown = get_campaign_funding_breakdown(id);
+        assert_eq!((breakdown.icp_e8s, breakdown.fiat_amount, breakdown.total), (250, 0, 250), "still counted as ICP");
+        as_caller(user(1));
+        assert_eq!(create_campaign_with_duration(idea_id, 2_000, WEEK_SECS), Ok(id + 1), "ids continue");
+    }
+
+    #[test]
+    fn new_campaigns_are_in_legacy_units_until_a_currency_is_set() {
+        let idea_id = new_idea();
+        let id = create_campaign(idea_id, 1_000, now_secs() + WEEK_SECS, Vec::new(), None).unwrap();
+        // Fund_Flow counts fiat toward a campaign without a currency, but never toward an ICP goal
+        let meta = get_campaign_meta(id).unwrap();
+        assert_eq!((meta.currency, meta.decimals), (None, None));
+
+        set_campaign_currency(id, " egp ".into(), 2, 50_000).unwrap();
+        let meta = get_campaign_meta(id).unwrap();
+        assert_eq!((meta.currency.as_deref(), meta.decimals, meta.goal), (Some("EGP"), Some(2), 50_000));
+    }
+
+    #[test]
+    fn a_rolled_over_contribution_counts_only_toward_its_new_campaign() {
+        configure_fund_flow();
+        set_max_active_campaigns_per_idea(2).unwrap();
+        let idea_id = new_idea();
+        let old = create_campaign_with_duration(idea_id, 1_000, WEEK_SECS).unwrap();
+        let new = create_campaign_with_duration(idea_id, 2_000, 2 * WEEK_SECS).unwrap();
+        as_caller(FUND_FLOW_ID);
+        receive_icp_contribution(old, 300, user(2), true).unwrap();
+        receive_icp_contribution(old, 100, user(3), true).unwrap();
+
+        // what Fund_Flow sends when user(2) rolls their 300 over
+        receive_icp_refund(old, 300).unwrap();
+        receive_icp_contribution(new, 300, user(2), true).unwrap();
+
+        let split = |id| {
+            let b = get_campaign_funding_breakdown(id);
+            (b.icp_e8s, b.total)
+        };
+        assert_eq!((split(old), split(new)), ((100, 100), (300, 300)));
+        assert!(matches!(get_campaign(old).unwrap().status, Some(CampaignStatus::Active)), "not refunded as a whole");
+        assert_eq!(get_idea(idea_id).unwrap().current_funding, 400);
+        as_caller(user(2));
+        assert!(receive_icp_refund(new, 300).is_err(), "Fund_Flow only");
+    }
+}
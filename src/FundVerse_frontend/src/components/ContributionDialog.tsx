@@ -69,12 +69,12 @@ export const ContributionDialog: React.FC<ContributionDialogProps> = ({
     setSuccess(false);
     
     try {
-      // First, register the user if not already registered
-      try {
-        await fundFlowActor.register_user('FundVerse User', 'user@fundverse.com');
-      } catch (error) {
-        // User might already be registered, continue
-        console.log('User registration skipped (likely already registered):', error);
+      // First, register the user if not already registered (re-registering would overwrite their profile)
+      if (!(await fundFlowActor.is_registered([]))) {
+        const reg = await fundFlowActor.register_user('FundVerse User', 'user@fundverse.com');
+        if ('Err' in reg) {
+          throw new Error(reg.Err);
+        }
       }
 
       // Get backend canister principal
@@ -84,7 +84,9 @@ export const ContributionDialog: React.FC<ContributionDialogProps> = ({
       const result = await fundFlowActor.contribute_icp(
         backendPrincipal,
         campaignId,
-        BigInt(amountE8s)
+        BigInt(amountE8s),
+        [],                         // no tip
+        []                          // no partial fill
       );
 
       if ('Err' in result) {
@@ -23,8 +23,8 @@ import { Plus, Loader2 } from 'lucide-react';
 
 
 const createProjectSchema = z.object({
-  title: z.string().min(1, 'Title is required').max(100, 'Title must be less than 100 characters'),
-  description: z.string().min(10, 'Description must be at least 10 characters').max(500, 'Description must be less than 500 characters'),
+  title: z.string().min(3, 'Title must be at least 3 characters').max(100, 'Title must be less than 100 characters'),
+  description: z.string().min(50, 'Description must be at least 50 characters').max(500, 'Description must be less than 500 characters'),
   fundingGoal: z.string().min(1, 'Funding goal is required'),
   legalEntity: z.string().min(1, 'Legal entity is required'),
   contactInfo: z.string().email('Valid email is required'),
@@ -76,7 +76,7 @@ const onSubmit = async (data: CreateProjectForm) => {
     const businessRegNat8 = Number(data.businessRegistration) & 0xff;
 
     // 3) create idea (بيرجع nat64 -> bigint في تايب سكريبت)
-    const ideaId: bigint = await backendActor.create_idea(
+    const ideaRes = await backendActor.create_idea(
       data.title,
       data.description,
       BigInt(fundingGoalE8s),
@@ -85,6 +85,10 @@ const onSubmit = async (data: CreateProjectForm) => {
       data.category,
       businessRegNat8
     );
+    if ('Err' in ideaRes) {
+      throw new Error(ideaRes.Err);
+    }
+    const ideaId: bigint = ideaRes.Ok;
 
     // 4) نحسب end_date بعد 30 يوم (بالـ seconds)
     const nowSecs = Math.floor(Date.now() / 1000);
@@ -95,7 +99,9 @@ const onSubmit = async (data: CreateProjectForm) => {
       // create_campaign بياخد nat64 → ابعتي BigInt
       BigInt(ideaId),                // idea_id
       BigInt(fundingGoalE8s),        // goal (e8s)
-      BigInt(endDateSecs)            // end_date (seconds)
+      BigInt(endDateSecs),           // end_date (seconds)
+      [],                            // labels
+      []                             // slug
     );
 
     // 6) تعامل مع Result { Ok | Err }
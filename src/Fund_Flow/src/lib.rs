@@ -5,27 +5,44 @@
 //! - Supports ICP coin transfers via ledger canister
 //! - Admin/owner confirms payments (Pending -> Held)
 //! - Release/refund logic uses backend metadata via inter-canister calls
+//!
 //! NOTE: adapt backend method names in the `notify_backend_*` functions
 //! to match your backend implementation.
 
 use candid::{CandidType, Decode, Deserialize, Encode, Principal, Nat};
-use ic_cdk::api::call::call;
-use ic_cdk_macros::{init, query, update};
+use ic_cdk::api::call::{call, RejectionCode};
+use ic_cdk_macros::{init, post_upgrade, query, update};
 use ic_stable_structures::{
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
     storable::Bound,
-    DefaultMemoryImpl, StableBTreeMap, Storable,
+    DefaultMemoryImpl, StableBTreeMap, StableCell, Storable,
 };
 use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 // ---------- Type aliases ----------
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
 // ---------- Config ----------
 const MAX_VALUE_SIZE: u32 = 8 * 1024; // 8KB per value (MVP)
+const MAX_PAGE_LIMIT: u64 = 200; // upper bound for `limit` on listing queries
+const MAX_BATCH_SIZE: usize = 100; // upper bound for ids in batch queries
+const ICP_DECIMALS: u8 = 8; // ICP contribution amounts are e8s
+const FIAT_DECIMALS: u8 = 2; // other methods record the campaign currency's minor unit (e.g. piastres)
+const MAX_REASON_LEN: usize = 500;
+const MAX_METHOD_NAME_LEN: usize = 32; // inner name of `PaymentMethod::Other`
+const SETTLED_RELEASED: u8 = 1;
+const SETTLED_REFUNDED: u8 = 2;
+const MAX_NOTIFY_ATTEMPTS: u32 = 10; // outbox entries past this are kept for inspection but no longer retried
+const MAX_BACKEND_FANOUT: usize = 20; // upper bound for per-campaign backend calls in one message
 const CANISTER_VERSION: &str = "funding-canister-v1";
+const SCHEMA_VERSION: u32 = 3; // layout of the stable maps, see `migrate_schema`
+const CANDID_SCHEMA: &str = include_str!("../Fund_Flow.did");
+// Fixed ICP ledger fee, always paid by the sender on top of the amount. Inbound: the backer pays
+// it, so escrow receives the full contribution. Outbound (payouts, refunds): escrow pays it, so the
+// recipient gets the escrowed amount minus one fee per transfer.
+const ICP_LEDGER_FEE_E8S: u64 = 10_000;
 const LEDGER_CANISTER_ID: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai"; // Mainnet ledger
 // const LEDGER_CANISTER_ID: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai"; // Local ledger for testing
 
@@ -39,6 +56,12 @@ thread_local! {
         StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(0))))
     );
 
+    // Last contribution id handed out; ids are never reused, even if a record is removed. Memory 22.
+    static LAST_CONTRIBUTION_ID: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(22))), 0)
+            .expect("init contribution id cell")
+    );
+
     // Registered users: key = Pk (wrapper) -> RegisteredUser
     static USERS: RefCell<StableBTreeMap<Pk, RegisteredUser, Memory>> = RefCell::new(
         StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(1))))
@@ -49,14 +72,147 @@ thread_local! {
         StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(2))))
     );
 
-    // Simple counter for contribution ids (in stable map we keep as length+1)
-    // We'll compute id = len + 1 when inserting
+    // Last ICP transfer id handed out, independent of contribution ids; never reused. Memory 23.
+    static LAST_TRANSFER_ID: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(23))), 0)
+            .expect("init transfer id cell")
+    );
+
+    // Canister owner (the installer), set in `init`
+    static OWNER: RefCell<StableCell<Principal, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(3))), Principal::anonymous())
+            .expect("init owner cell")
+    );
+
+    // Payment gateway principals allowed to confirm payments: Pk -> added_at_ns
+    static GATEWAYS: RefCell<StableBTreeMap<Pk, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(4))))
+    );
+
+    // Event log polled by off-chain listeners (webhooks, email): event_id -> FundEvent
+    static EVENTS: RefCell<StableBTreeMap<u64, FundEvent, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(5))))
+    );
+
+    // Highest funding progress band (percent) already announced per campaign: campaign_id -> percent
+    static PROGRESS_CROSSED: RefCell<StableBTreeMap<u64, u8, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(6))))
+    );
+
+    // Sponsor match pools: campaign_id -> MatchPool
+    static MATCH_POOLS: RefCell<StableBTreeMap<u64, MatchPool, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(7))))
+    );
+
+    // Seconds a backer must wait before re-contributing to a campaign that refunded them (0 = disabled)
+    static RE_CONTRIBUTE_COOLDOWN_SECS: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(8))), 0)
+            .expect("init cooldown cell")
+    );
+
+    // ICP contributions whose transfer link failed the post-upgrade check: contribution_id -> reason
+    static ICP_LINK_ISSUES: RefCell<StableBTreeMap<u64, String, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(9))))
+    );
+
+    // Seconds a contribution may stay Pending before `expire_stale_pending` expires it (0 = never)
+    static PENDING_TTL_SECS: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(10))), 0)
+            .expect("init pending ttl cell")
+    );
+
+    // Emergency pause: when true, non-owner state-changing calls are rejected
+    static PAUSED: RefCell<StableCell<bool, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(11))), false)
+            .expect("init paused cell")
+    );
+
+    // Backend notifications that failed transiently, awaiting `flush_outbox`
+    static OUTBOX: RefCell<StableBTreeMap<u64, PendingNotification, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(12))))
+    );
+
+    // Terminal settlement per campaign: campaign_id -> SETTLED_RELEASED | SETTLED_REFUNDED
+    static SETTLEMENTS: RefCell<StableBTreeMap<u64, u8, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(13))))
+    );
+
+    // Receives platform tips; anonymous means "use the owner"
+    static FEE_COLLECTOR: RefCell<StableCell<Principal, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(14))), Principal::anonymous())
+            .expect("init fee collector cell")
+    );
+
+    // Schema version the stable data was written with (0 = before versioning). Memory 15.
+    static STORED_SCHEMA_VERSION: RefCell<StableCell<u32, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(15))), 0)
+            .expect("init schema version cell")
+    );
+
+    // Which contribution events each backer wants listeners to see: Pk -> NotificationPrefs. Memory 16.
+    static NOTIFICATION_PREFS: RefCell<StableBTreeMap<Pk, NotificationPrefs, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(16))))
+    );
+
+    // Seconds past a campaign's end_date that contributions are still accepted, so late-settling
+    // payments (bank transfers) can land; release and refunds wait for it too. Memory 17.
+    static SETTLEMENT_GRACE_SECS: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(17))), 0)
+            .expect("init settlement grace cell")
+    );
+
+    // Fiat refunds awaiting an off-chain operator: contribution_id -> RefundTask. Memory 18.
+    static REFUND_QUEUE: RefCell<StableBTreeMap<u64, RefundTask, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(18))))
+    );
+
+    // ICP refunds smaller than this many e8s are not sent back on the ledger. Memory 21.
+    static MIN_REFUNDABLE_E8S: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(21))), 0)
+            .expect("init min refundable cell")
+    );
+
+    // KYC thresholds: contributions above these need a verified user (0 = no limit).
+    // ICP in e8s (memory 19) and fiat in minor units such as piastres (memory 20).
+    static KYC_THRESHOLD_E8S: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(19))), 0)
+            .expect("init kyc icp threshold cell")
+    );
+    static KYC_THRESHOLD_FIAT: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|mm| mm.borrow().get(MemoryId::new(20))), 0)
+            .expect("init kyc fiat threshold cell")
+    );
+
+    // Campaigns with a release in flight (heap only: nothing is in flight across an upgrade)
+    static SETTLING: RefCell<BTreeSet<u64>> = const { RefCell::new(BTreeSet::new()) };
 }
 
 // ---------- Helpers ----------
+// The system API panics off-wasm, so unit tests read the clock, caller and canister id from `mock`.
+#[cfg(not(test))]
 fn now_ns() -> u64 {
     ic_cdk::api::time()
 }
+#[cfg(test)]
+fn now_ns() -> u64 {
+    mock::NOW_NS.with(|n| n.get())
+}
+#[cfg(not(test))]
+fn caller() -> Principal {
+    ic_cdk::api::caller()
+}
+#[cfg(test)]
+fn caller() -> Principal {
+    mock::CALLER.with(|c| c.get())
+}
+#[cfg(not(test))]
+fn canister_id() -> Principal {
+    ic_cdk::api::id()
+}
+#[cfg(test)]
+fn canister_id() -> Principal {
+    mock::CANISTER_ID
+}
 fn now_secs() -> u64 {
     now_ns() / 1_000_000_000
 }
@@ -69,6 +225,11 @@ impl From<Principal> for Pk {
         Self(p.as_slice().to_vec())
     }
 }
+impl From<Pk> for Principal {
+    fn from(pk: Pk) -> Self {
+        Principal::from_slice(&pk.0)
+    }
+}
 impl Storable for Pk {
     fn to_bytes(&self) -> Cow<[u8]> {
         Cow::Owned(self.0.clone())
@@ -79,6 +240,82 @@ impl Storable for Pk {
     const BOUND: Bound = Bound::Unbounded;
 }
 
+// ---------- Access control ----------
+fn owner() -> Principal {
+    OWNER.with(|o| *o.borrow().get())
+}
+
+fn is_gateway(p: Principal) -> bool {
+    GATEWAYS.with(|g| g.borrow().contains_key(&Pk::from(p)))
+}
+
+fn fee_collector() -> Principal {
+    let p = FEE_COLLECTOR.with(|f| *f.borrow().get());
+    if p == Principal::anonymous() { owner() } else { p }
+}
+
+/// Canisters installed before `OWNER` existed come up with it anonymous, which would leave every
+/// owner-only endpoint unreachable. Only a controller can upgrade, so the upgrader claims it.
+fn seed_owner_on_upgrade() {
+    if owner() == Principal::anonymous() {
+        let upgrader = caller();
+        OWNER.with(|o| o.borrow_mut().set(upgrader).expect("set owner"));
+        ic_cdk::println!("owner unset; claimed by upgrading controller {}", upgrader);
+    }
+}
+
+fn ensure_owner() -> Result<(), String> {
+    if caller() == owner() {
+        Ok(())
+    } else {
+        Err("only the owner can do this".into())
+    }
+}
+
+fn ensure_registered(p: Principal) -> Result<(), String> {
+    if USERS.with(|u| u.borrow().contains_key(&Pk::from(p))) {
+        Ok(())
+    } else {
+        Err("Only registered users can contribute".into())
+    }
+}
+
+/// Reject an `amount` above the KYC threshold for its method unless `p` is verified.
+/// ICP amounts are e8s, fiat amounts the currency's minor unit, as recorded on the contribution.
+fn ensure_verified_for(p: Principal, method: &PaymentMethod, amount: u64) -> Result<(), String> {
+    let (icp, fiat) = get_kyc_thresholds();
+    let threshold = if *method == PaymentMethod::ICP { icp } else { fiat };
+    if threshold == 0 || amount <= threshold {
+        return Ok(());
+    }
+    let verified = USERS.with(|u| u.borrow().get(&Pk::from(p))).and_then(|u| u.verified).unwrap_or(false);
+    if verified {
+        Ok(())
+    } else {
+        Err("verification required".into())
+    }
+}
+
+/// Who may move a Pending contribution to Held: the owner, an allowlisted gateway, or the
+/// backer of an ICP contribution (which is verified against its transfer record).
+fn ensure_can_confirm(c: &Contribution) -> Result<(), String> {
+    let caller = caller();
+    let backer_confirming_icp = c.method == PaymentMethod::ICP && caller == c.backer;
+    if caller == owner() || is_gateway(caller) || backer_confirming_icp {
+        Ok(())
+    } else {
+        Err("not authorized to confirm".into())
+    }
+}
+
+fn ensure_not_paused() -> Result<(), String> {
+    if PAUSED.with(|p| *p.borrow().get()) {
+        Err("canister paused".into())
+    } else {
+        Ok(())
+    }
+}
+
 // ---------- Data models ----------
 #[derive(CandidType, Deserialize, Clone, Debug , PartialEq, Eq)]
 pub enum PaymentMethod {
@@ -95,6 +332,8 @@ pub enum EscrowStatus {
     Held,    // payment confirmed and held in escrow
     Released,// paid out to project owner
     Refunded,// returned to backer
+    Expired, // stayed Pending longer than the pending TTL without payment
+    Cancelled, // Pending contribution dropped because its campaign was cancelled
 }
 
 #[derive(CandidType, Deserialize, Clone, Debug)]
@@ -108,6 +347,13 @@ pub struct Contribution {
     pub created_at_ns: u64,
     pub confirmed_at_ns: Option<u64>,
     pub icp_transfer_id: Option<u64>, // Link to ICP transfer if method is ICP
+    pub matched_for: Option<u64>,     // Backer contribution this sponsor match belongs to
+    pub imported_at_ns: Option<u64>,  // Set when seeded via `import_contributions` (migrated/off-chain)
+    pub refunded_at_ns: Option<u64>,
+    pub tax_rate_bps: Option<u16>,    // VAT rate of a tax-applicable campaign at contribution time
+    pub tax_amount: Option<u64>,      // VAT portion included in `amount` (which stays gross)
+    pub tip_e8s: Option<u64>,         // Platform tip paid to the fee collector on top of `amount`
+    pub rolled_over_from: Option<u64>, // Refunded contribution whose escrowed funds this one reuses
 }
 impl Storable for Contribution {
     fn to_bytes(&self) -> Cow<[u8]> {
@@ -125,6 +371,8 @@ pub struct RegisteredUser {
     pub name: String,
     pub email: String,
     pub registered_at_ns: u64,
+    pub verified: Option<bool>,       // KYC passed (owner-set); None for users stored before KYC
+    pub verified_at_ns: Option<u64>,  // when `verified` last changed
 }
 impl Storable for RegisteredUser {
     fn to_bytes(&self) -> Cow<[u8]> {
@@ -136,6 +384,20 @@ impl Storable for RegisteredUser {
     const BOUND: Bound = Bound::Bounded { max_size: MAX_VALUE_SIZE, is_fixed_size: false };
 }
 
+/// A contribution joined with its ICP transfer, for payment-status pages.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ContributionDetail {
+    pub contribution: Contribution,
+    pub transfer: Option<ICPTransfer>,
+}
+
+/// Reply of `register_user`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Registration {
+    pub user: RegisteredUser,
+    pub newly_registered: bool,
+}
+
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct ICPTransfer {
     pub id: u64,
@@ -147,6 +409,7 @@ pub struct ICPTransfer {
     pub status: ICPTransferStatus,
     pub created_at_ns: u64,
     pub confirmed_at_ns: Option<u64>,
+    pub fee_e8s: Option<u64>, // ledger fee paid by `from`; None on transfers recorded before fees were tracked
 }
 impl Storable for ICPTransfer {
     fn to_bytes(&self) -> Cow<[u8]> {
@@ -165,6 +428,180 @@ pub enum ICPTransferStatus {
     Failed,
 }
 
+/// A sponsor matching ICP contributions to a campaign, up to `remaining_e8s`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MatchPool {
+    pub sponsor: Principal,
+    pub match_ratio_bps: u16, // 10_000 = 1:1 match
+    pub remaining_e8s: u64,
+}
+impl Storable for MatchPool {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("encode match pool"))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("decode match pool")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: MAX_VALUE_SIZE, is_fixed_size: false };
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum FundEventKind {
+    ProgressThreshold { campaign_id: u64, percent: u8 },
+    ForceReleased { campaign_id: u64, total_amount: u64, by: Principal, reason: String },
+    /// A contribution became Held (confirmed), Released or Refunded; subject to the backer's prefs.
+    ContributionStatusChanged { contribution_id: u64, campaign_id: u64, backer: Principal, status: EscrowStatus },
+    PendingCancelled { campaign_id: u64, count: u64, by: Principal },
+    /// The ledger memo of a transfer names another campaign than the contribution it backs.
+    TransferMemoMismatch { transfer_id: u64, campaign_id: u64, memo: u64 },
+    OwnershipTransferred { from: Principal, to: Principal, by: Principal },
+}
+
+/// Which events about their own contributions a backer wants emitted. All on by default.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct NotificationPrefs {
+    pub on_confirm: bool,
+    pub on_release: bool,
+    pub on_refund: bool,
+}
+impl Default for NotificationPrefs {
+    fn default() -> Self {
+        Self { on_confirm: true, on_release: true, on_refund: true }
+    }
+}
+impl Storable for NotificationPrefs {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("encode notification prefs"))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("decode notification prefs")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: MAX_VALUE_SIZE, is_fixed_size: false };
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct FundEvent {
+    pub id: u64,
+    pub kind: FundEventKind,
+    pub at_ns: u64,
+}
+impl Storable for FundEvent {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("encode event"))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("decode event")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: MAX_VALUE_SIZE, is_fixed_size: false };
+}
+
+/// A historical (already paid) contribution to seed via `import_contributions`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ImportedContribution {
+    pub campaign_id: u64,
+    pub backer: Principal,
+    pub amount: u64,
+    pub method: PaymentMethod,
+    pub created_at_ns: u64,
+}
+
+/// Platform-wide totals.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GlobalStats {
+    pub contributions: u64,
+    pub users: u64,
+    pub campaigns_backed: u64,
+    pub total_tips_e8s: u64, // tips on contributions that were confirmed
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct VersionInfo {
+    pub version: String,
+    pub candid_schema_hash: String, // hex FNV-1a of Fund_Flow.did
+    pub build_time: Option<String>,
+}
+
+/// Operator snapshot returned by `debug_dump`; principals are text so they paste into `dfx`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct DebugInfo {
+    pub version: String,
+    pub canister_id: String,
+    pub owner: String,
+    pub ledger: String,
+    pub fee_collector: String, // where tips go; the owner until `set_fee_collector`
+    pub gateways: Vec<String>,
+    pub paused: bool,
+    pub contributions: u64,
+    pub users: u64,
+    pub icp_transfers: u64,
+    pub events: u64,
+    pub pending_notifications: u64,
+}
+
+/// Ordering for `get_contributions_by_user_sorted`.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum SortField {
+    CreatedAt,
+    ConfirmedAt,
+    Amount,
+}
+
+/// ICRC-10 standard descriptor: a name like "ICRC-1" and a link to its specification.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SupportedStandard {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum BackendNotification {
+    IcpContribution { campaign_id: u64, amount_e8s: u64, backer: Principal, is_new_backer: bool },
+    Payout { campaign_id: u64, total_amount: u64 },
+    Refund { campaign_id: u64, total_amount: u64 },
+    /// One ICP contribution left the campaign without settling it (rolled over to another one).
+    IcpRefund { campaign_id: u64, amount_e8s: u64 },
+}
+
+/// A backend notification queued after a transient failure.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PendingNotification {
+    pub id: u64,
+    pub backend: Principal,
+    pub notification: BackendNotification,
+    pub attempts: u32,
+    pub last_error: String,
+    pub queued_at_ns: u64,
+}
+impl Storable for PendingNotification {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("encode pending notification"))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("decode pending notification")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: MAX_VALUE_SIZE, is_fixed_size: false };
+}
+
+/// A refund that has to be paid back outside the canister (bank transfer, Fawry, ...).
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct RefundTask {
+    pub contribution_id: u64,
+    pub campaign_id: u64,
+    pub method: PaymentMethod,
+    pub amount: u64, // in the contribution's own units
+    pub backer: Principal,
+    pub queued_at_ns: u64,
+}
+impl Storable for RefundTask {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("encode refund task"))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("decode refund task")
+    }
+    const BOUND: Bound = Bound::Bounded { max_size: MAX_VALUE_SIZE, is_fixed_size: false };
+}
+
 // ---------- Inter-canister types (expected response from backend) ----------
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct CampaignMeta {
@@ -172,16 +609,76 @@ pub struct CampaignMeta {
     pub goal: u64,
     pub amount_raised: u64,
     pub end_date_secs: u64, // seconds since epoch
+    pub owner_wallet: Option<Principal>, // payout destination set by the campaign creator
+    // Unit of `goal`: smallest unit of `currency` with `decimals` digits. `None` from older backends.
+    pub currency: Option<String>,
+    pub decimals: Option<u8>,
+    pub tax_rate_bps: Option<u16>, // VAT included in contributions; 0/`None` = not tax-applicable
+    pub combined_max_per_backer: Option<u64>, // in goal units, across all methods
+    pub creator: Option<Principal>, // campaign creator; `None` from older backends
+    pub closed_at: Option<u64>, // set when the creator closed the campaign early
+}
+
+/// Mirror of the backend's `CampaignCard`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CampaignCard {
+    pub id: u64,
+    pub idea_id: u64,
+    pub title: String,
+    pub category: String,
+    pub amount_raised: u64,
+    pub goal: u64,
+    pub end_date: u64,
+    pub days_left: u64,
+    pub labels: Vec<String>,
+    pub backer_count: u64,
+}
+
+/// Mirror of the backend's `Idea`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Idea {
+    pub title: String,
+    pub description: String,
+    pub funding_goal: u64,
+    pub current_funding: u64,
+    pub legal_entity: String,
+    pub status: Option<String>,
+    pub contact_info: String,
+    pub category: String,
+    pub business_registration: u8,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub doc_ids: Vec<u64>,
+    pub owner: Option<Principal>,
+}
+
+/// Mirror of the backend's `CampaignWithIdea`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CampaignWithIdea {
+    pub campaign: CampaignCard,
+    pub idea: Idea,
 }
 
 // ---------- Internal helpers for stable maps ----------
 
+/// Allocate the next contribution id from the stable counter.
 fn next_contribution_id() -> u64 {
-    CONTRIBUTIONS.with(|m| (m.borrow().len() as u64) + 1)
+    LAST_CONTRIBUTION_ID.with(|c| {
+        let mut c = c.borrow_mut();
+        let id = *c.get() + 1;
+        c.set(id).expect("set contribution id");
+        id
+    })
 }
 
+/// Allocate the next ICP transfer id from the stable counter.
 fn next_transfer_id() -> u64 {
-    ICP_TRANSFERS.with(|m| (m.borrow().len() as u64) + 1)
+    LAST_TRANSFER_ID.with(|c| {
+        let mut c = c.borrow_mut();
+        let id = *c.get() + 1;
+        c.set(id).expect("set transfer id");
+        id
+    })
 }
 
 fn insert_contribution(c: Contribution) {
@@ -200,6 +697,128 @@ fn update_contribution(id: u64, c: Contribution) {
     });
 }
 
+/// Pending sponsor match recorded alongside the given backer contribution, if any.
+fn find_match_for(contribution_id: u64) -> Option<Contribution> {
+    CONTRIBUTIONS.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, v)| v)
+            .find(|v| v.matched_for == Some(contribution_id))
+    })
+}
+
+/// Take the sponsor's share for a backer contribution out of the campaign's match pool.
+/// Returns the sponsor and matched amount, or None if there is no pool or it is exhausted.
+fn draw_from_match_pool(campaign_id: u64, amount_e8s: u64) -> Option<(Principal, u64)> {
+    MATCH_POOLS.with(|m| {
+        let mut m = m.borrow_mut();
+        let mut pool = m.get(&campaign_id)?;
+        let wanted = ((amount_e8s as u128) * (pool.match_ratio_bps as u128) / 10_000) as u64;
+        let matched = wanted.min(pool.remaining_e8s);
+        if matched == 0 {
+            return None;
+        }
+        pool.remaining_e8s -= matched;
+        let sponsor = pool.sponsor;
+        m.insert(campaign_id, pool);
+        Some((sponsor, matched))
+    })
+}
+
+/// Put a sponsor match that will never be confirmed (expired or cancelled) back into its pool.
+fn return_to_match_pool(c: &Contribution) {
+    if c.matched_for.is_none() {
+        return;
+    }
+    MATCH_POOLS.with(|m| {
+        let mut m = m.borrow_mut();
+        if let Some(mut pool) = m.get(&c.campaign_id) {
+            pool.remaining_e8s = pool.remaining_e8s.saturating_add(c.amount);
+            m.insert(c.campaign_id, pool);
+        }
+    });
+}
+
+/// Whether the backend has already been told about an ICP contribution from `backer` to
+/// `campaign_id`, i.e. one that reached Held (and possibly Released or Refunded since).
+fn has_counted_icp_backing(backer: Principal, campaign_id: u64) -> bool {
+    CONTRIBUTIONS.with(|m| {
+        m.borrow().iter().any(|(_, c)| {
+            c.backer == backer
+                && c.campaign_id == campaign_id
+                && c.method == PaymentMethod::ICP
+                && c.matched_for.is_none()
+                && c.confirmed_at_ns.is_some()
+        })
+    })
+}
+
+/// Validate a caller-supplied payment method. `Other` names are trimmed and lowercased, and may
+/// not be empty or spell a built-in method: `Other("icp")` is never ICP, so it's refused rather
+/// than recorded as a look-alike. Method-specific logic matches on the variant only.
+fn normalize_method(method: PaymentMethod) -> Result<PaymentMethod, String> {
+    let PaymentMethod::Other(name) = method else {
+        return Ok(method);
+    };
+    let name = name.trim().to_lowercase();
+    if name.is_empty() || name.chars().count() > MAX_METHOD_NAME_LEN {
+        return Err(format!("payment method name must be 1-{} characters", MAX_METHOD_NAME_LEN));
+    }
+    let compact: String = name.chars().filter(|ch| ch.is_ascii_alphanumeric()).collect();
+    if ["icp", "banktransfer", "fawry", "paymob"].contains(&compact.as_str()) {
+        return Err(format!("\"{}\" is a built-in payment method; use its variant instead of Other", name));
+    }
+    Ok(PaymentMethod::Other(name))
+}
+
+/// Reject a contribution of `units` (goal units) that would take the backer's non-refunded total
+/// in the campaign, over every payment method, past `combined_max_per_backer`.
+fn check_backer_cap(backer: Principal, units: u64, meta: &CampaignMeta) -> Result<(), String> {
+    let Some(cap) = meta.combined_max_per_backer else {
+        return Ok(());
+    };
+    let existing = CONTRIBUTIONS.with(|m| {
+        m.borrow()
+            .iter()
+            .filter(|(_, c)| {
+                c.campaign_id == meta.campaign_id
+                    && c.backer == backer
+                    && c.matched_for.is_none()
+                    && c.status != EscrowStatus::Refunded
+            })
+            .map(|(_, c)| to_campaign_units(&c.method, c.amount, meta).unwrap_or(0))
+            .fold(0u64, |acc, a| acc.saturating_add(a))
+    });
+    if existing.saturating_add(units) > cap {
+        return Err(format!(
+            "contribution would exceed this campaign's per-backer cap of {} ({} already contributed)",
+            cap, existing
+        ));
+    }
+    Ok(())
+}
+
+/// Reject a backer who was refunded by this campaign within the re-contribute cooldown.
+fn check_refund_cooldown(backer: Principal, campaign_id: u64) -> Result<(), String> {
+    let cooldown_ns = RE_CONTRIBUTE_COOLDOWN_SECS.with(|c| *c.borrow().get()).saturating_mul(1_000_000_000);
+    if cooldown_ns == 0 {
+        return Ok(());
+    }
+    let now = now_ns();
+    let recently_refunded = CONTRIBUTIONS.with(|m| {
+        m.borrow().iter().any(|(_, c)| {
+            c.backer == backer
+                && c.campaign_id == campaign_id
+                && c.refunded_at_ns.is_some_and(|t| now.saturating_sub(t) < cooldown_ns)
+        })
+    });
+    if recently_refunded {
+        Err("recently refunded by this campaign; try again later".into())
+    } else {
+        Ok(())
+    }
+}
+
 fn insert_icp_transfer(t: ICPTransfer) {
     ICP_TRANSFERS.with(|m| {
         m.borrow_mut().insert(t.id, t);
@@ -216,144 +835,902 @@ fn update_icp_transfer(id: u64, t: ICPTransfer) {
     });
 }
 
-// ---------- ICP Ledger Integration ----------
+// ---------- Settlement ----------
 
-/// Create an ICP transfer record and initiate the transfer
-async fn initiate_icp_transfer(from: Principal, to: Principal, amount_e8s: u64, memo: u64) -> Result<u64, String> {
-    let transfer_id = next_transfer_id();
-    
-    let transfer = ICPTransfer {
-        id: transfer_id,
-        from,
-        to,
-        amount_e8s,
-        memo,
-        block_height: None,
-        status: ICPTransferStatus::Pending,
-        created_at_ns: now_ns(),
-        confirmed_at_ns: None,
-    };
-    
-    insert_icp_transfer(transfer);
-    
-    // For now, we'll simulate the transfer since we need proper ledger integration
-    // In a real implementation, you would call the ledger canister here
-    
-    // Simulate successful transfer for testing
-    if let Some(mut transfer) = get_icp_transfer(transfer_id) {
-        transfer.block_height = Some(12345); // Simulated block height
-        transfer.status = ICPTransferStatus::Confirmed;
-        transfer.confirmed_at_ns = Some(now_ns());
-        update_icp_transfer(transfer_id, transfer);
+/// Released and refunded are terminal for a campaign: once one happened, neither may run again.
+fn ensure_not_settled(campaign_id: u64) -> Result<(), String> {
+    if SETTLEMENTS.with(|m| m.borrow().contains_key(&campaign_id)) {
+        Err("campaign already settled".into())
+    } else {
+        Ok(())
     }
-    
-    Ok(transfer_id)
 }
 
-/// Check if an ICP transfer has been confirmed
-async fn check_icp_transfer_status(transfer_id: u64) -> Result<ICPTransferStatus, String> {
-    if let Some(transfer) = get_icp_transfer(transfer_id) {
-        Ok(transfer.status)
+/// Marks a campaign's release as in flight until dropped.
+///
+/// Reentrancy hazard: `release_held` and `refund_campaign` await the backend and the ledger, and
+/// other messages run during each await. Without this lock a refund interleaved with a release
+/// could flip the same Held contributions to Refunded. The guard is dropped on every
+/// exit path, including when ic-cdk cleans up after a trap in a callback.
+struct SettlementGuard(u64);
+
+impl SettlementGuard {
+    fn acquire(campaign_id: u64) -> Result<Self, String> {
+        if SETTLING.with(|s| s.borrow_mut().insert(campaign_id)) {
+            Ok(SettlementGuard(campaign_id))
+        } else {
+            Err("campaign settlement in progress".into())
+        }
+    }
+}
+
+impl Drop for SettlementGuard {
+    fn drop(&mut self) {
+        SETTLING.with(|s| s.borrow_mut().remove(&self.0));
+    }
+}
+
+fn ensure_not_settling(campaign_id: u64) -> Result<(), String> {
+    if SETTLING.with(|s| s.borrow().contains(&campaign_id)) {
+        Err("campaign settlement in progress".into())
     } else {
-        Err("Transfer not found".into())
+        Ok(())
     }
 }
 
-// ---------- Inter-canister call helpers ----------
+fn mark_settled(campaign_id: u64, settlement: u8) {
+    SETTLEMENTS.with(|m| m.borrow_mut().insert(campaign_id, settlement));
+}
 
-/// Fetch campaign meta from backend canister.
-/// Expects backend to expose `get_campaign_meta: (nat64) -> (opt record { campaign_id, goal, amount_raised, end_date_secs }) query`
-async fn fetch_campaign_meta(backend: Principal, campaign_id: u64) -> Result<Option<CampaignMeta>, String> {
-    // We expect the backend to return `opt CampaignMeta` (encoded as Option)
-    let res: Result<(Option<CampaignMeta>,), _> = call(backend, "get_campaign_meta", (campaign_id,)).await;
-    match res {
-        Ok((meta_opt,)) => Ok(meta_opt),
-        Err(e) => Err(format!("backend call failed: {:?}", e)),
+// ---------- Units ----------
+//
+// Contract: a campaign's goal is in the smallest unit of its currency (`CampaignMeta::currency`
+// with `decimals` digits). ICP contributions are e8s; bank/Fawry/PayMob contributions are in the
+// campaign currency at `FIAT_DECIMALS`. There is no exchange rate, so ICP only counts toward ICP
+// goals and fiat only toward fiat goals.
+
+/// A contribution `amount` paid via `method`, expressed in the campaign's goal unit.
+/// Backends that don't report a currency get the raw amount, as before units were tracked.
+fn to_campaign_units(method: &PaymentMethod, amount: u64, meta: &CampaignMeta) -> Result<u64, String> {
+    let (Some(currency), Some(decimals)) = (meta.currency.as_deref(), meta.decimals) else {
+        return Ok(amount);
+    };
+    let from_decimals = match method {
+        PaymentMethod::ICP if currency == "ICP" => ICP_DECIMALS,
+        PaymentMethod::ICP => return Err(format!("ICP can't be counted toward a {} goal", currency)),
+        _ if currency == "ICP" => return Err("fiat payments can't be counted toward an ICP goal".into()),
+        _ => FIAT_DECIMALS,
+    };
+    if decimals >= from_decimals {
+        10u64
+            .checked_pow((decimals - from_decimals) as u32)
+            .and_then(|factor| amount.checked_mul(factor))
+            .ok_or_else(|| "amount overflows campaign units".to_string())
+    } else {
+        Ok(amount / 10u64.pow((from_decimals - decimals) as u32))
     }
 }
 
-/// Notify backend that campaign should be credited/payout executed.
-/// This function calls backend method `receive_payout(campaign_id: nat64, total_amount: nat64) -> ()`
-/// **Make sure your backend implements `receive_payout` (or change this name)**.
-async fn notify_backend_receive_payout(backend: Principal, campaign_id: u64, total_amount: u64) -> Result<(), String> {
-    let res: Result<(), _> = call(backend, "receive_payout", (campaign_id, total_amount)).await;
-    match res {
-        Ok(()) => Ok(()),
-        Err(e) => Err(format!("notify backend failed: {:?}", e)),
+/// Largest ICP amount in e8s worth at most `units` of an ICP-denominated goal (inverse of
+/// `to_campaign_units`, rounding down so the result never exceeds `units`).
+fn icp_for_campaign_units(units: u64, meta: &CampaignMeta) -> u64 {
+    let Some(decimals) = meta.decimals.filter(|_| meta.currency.is_some()) else {
+        return units;
+    };
+    if decimals >= ICP_DECIMALS {
+        units / 10u64.pow((decimals - ICP_DECIMALS) as u32)
+    } else {
+        units.saturating_mul(10u64.pow((ICP_DECIMALS - decimals) as u32))
     }
 }
 
-/// Notify backend about ICP contribution
-async fn notify_backend_icp_contribution(backend: Principal, campaign_id: u64, amount_e8s: u64) -> Result<(), String> {
-    let res: Result<(), _> = call(backend, "receive_icp_contribution", (campaign_id, amount_e8s)).await;
-    match res {
-        Ok(()) => Ok(()),
-        Err(e) => Err(format!("notify backend ICP contribution failed: {:?}", e)),
+/// VAT contained in a gross `amount` for a tax-applicable campaign, as (rate, tax):
+/// tax = amount * rate / (10_000 + rate), rounded down. `(None, None)` when no VAT applies.
+fn vat_breakdown(amount: u64, meta: &CampaignMeta) -> (Option<u16>, Option<u64>) {
+    match meta.tax_rate_bps {
+        Some(bps) if bps > 0 => {
+            let tax = (amount as u128) * (bps as u128) / (10_000u128 + bps as u128);
+            (Some(bps), Some(tax as u64))
+        }
+        _ => (None, None),
     }
 }
 
-// ---------- Public API: Users ----------
+// ---------- Events ----------
 
-#[update]
-fn register_user(name: String, email: String) -> Result<(), String> {
-    let caller = ic_cdk::api::caller();
-    if name.trim().is_empty() || email.trim().is_empty() {
+/// Emit a `ContributionStatusChanged` event unless the backer opted out of that kind.
+fn emit_contribution_event(c: &Contribution) {
+    let prefs = NOTIFICATION_PREFS.with(|m| m.borrow().get(&Pk::from(c.backer))).unwrap_or_default();
+    let wanted = match c.status {
+        EscrowStatus::Held => prefs.on_confirm,
+        EscrowStatus::Released => prefs.on_release,
+        EscrowStatus::Refunded => prefs.on_refund,
+        _ => false,
+    };
+    if wanted {
+        emit_event(FundEventKind::ContributionStatusChanged {
+            contribution_id: c.id,
+            campaign_id: c.campaign_id,
+            backer: c.backer,
+            status: c.status.clone(),
+        });
+    }
+}
+
+/// Append an event to the log polled by off-chain listeners.
+fn emit_event(kind: FundEventKind) {
+    EVENTS.with(|m| {
+        let mut m = m.borrow_mut();
+        let id = m.last_key_value().map(|(k, _)| k + 1).unwrap_or(1);
+        m.insert(id, FundEvent { id, kind, at_ns: now_ns() });
+    });
+}
+
+/// Funding progress is announced in 25% bands.
+const PROGRESS_BAND_PERCENT: u8 = 25;
+const MAX_ANALYTICS_DAYS: u64 = 90;
+const ANALYTICS_TOP_BACKERS: usize = 10;
+
+/// Emit a `ProgressThreshold` event for every 25% band that Held + Released funds
+/// have crossed since the last announcement. Each band fires at most once per campaign,
+/// even if the total later drops (e.g. after a refund) and climbs back.
+fn record_progress(campaign_id: u64, meta: &CampaignMeta) {
+    let goal = meta.goal;
+    if goal == 0 {
+        return;
+    }
+    let mut funded: u64 = 0;
+    CONTRIBUTIONS.with(|m| {
+        for (_, c) in m.borrow().iter() {
+            if c.campaign_id == campaign_id
+                && (c.status == EscrowStatus::Held || c.status == EscrowStatus::Released)
+            {
+                funded = funded.saturating_add(to_campaign_units(&c.method, c.amount, meta).unwrap_or(0));
+            }
+        }
+    });
+
+    let percent = ((funded as u128) * 100 / (goal as u128)).min(100) as u8;
+    let band = percent / PROGRESS_BAND_PERCENT * PROGRESS_BAND_PERCENT;
+    let already = PROGRESS_CROSSED.with(|m| m.borrow().get(&campaign_id).unwrap_or(0));
+    if band <= already {
+        return;
+    }
+
+    let mut next = already + PROGRESS_BAND_PERCENT;
+    while next <= band {
+        emit_event(FundEventKind::ProgressThreshold { campaign_id, percent: next });
+        next += PROGRESS_BAND_PERCENT;
+    }
+    PROGRESS_CROSSED.with(|m| {
+        m.borrow_mut().insert(campaign_id, band);
+    });
+}
+
+// ---------- Integrity checks ----------
+
+/// Whether the ICP of `c` is sitting in escrow: its ledger transfer confirmed, or it was rolled over
+/// from a contribution whose ICP was. Such a contribution is real money even while still Pending.
+fn icp_in_escrow(c: &Contribution) -> bool {
+    c.method == PaymentMethod::ICP
+        && (c.rolled_over_from.is_some()
+            || c.icp_transfer_id
+                .and_then(get_icp_transfer)
+                .is_some_and(|t| t.status == ICPTransferStatus::Confirmed))
+}
+
+fn transfer_matches(c: &Contribution, t: &ICPTransfer) -> bool {
+    t.from == c.backer && t.amount_e8s == c.amount && t.memo == c.campaign_id
+}
+
+/// Verify every ICP contribution links to a transfer with the same backer, amount and campaign memo.
+/// A broken link is repaired when exactly one unclaimed transfer matches; otherwise the contribution
+/// is flagged in `ICP_LINK_ISSUES` for manual review. Returns the number of flagged contributions.
+fn check_icp_transfer_links() -> u64 {
+    let transfers: Vec<ICPTransfer> = ICP_TRANSFERS.with(|m| m.borrow().iter().map(|(_, t)| t).collect());
+    let linked: Vec<Contribution> = CONTRIBUTIONS.with(|m| {
+        m.borrow().iter().map(|(_, c)| c).filter(|c| c.icp_transfer_id.is_some()).collect()
+    });
+
+    let is_valid = |c: &Contribution| {
+        c.icp_transfer_id
+            .and_then(get_icp_transfer)
+            .is_some_and(|t| transfer_matches(c, &t))
+    };
+    let mut claimed: BTreeSet<u64> = linked
+        .iter()
+        .filter(|c| is_valid(c))
+        .filter_map(|c| c.icp_transfer_id)
+        .collect();
+
+    ICP_LINK_ISSUES.with(|m| {
+        let mut m = m.borrow_mut();
+        let stale: Vec<u64> = m.iter().map(|(k, _)| k).collect();
+        for k in stale {
+            m.remove(&k);
+        }
+    });
+
+    let mut flagged: u64 = 0;
+    for mut c in linked.into_iter().filter(|c| !is_valid(c)) {
+        let candidates: Vec<u64> = transfers
+            .iter()
+            .filter(|t| !claimed.contains(&t.id) && transfer_matches(&c, t))
+            .map(|t| t.id)
+            .collect();
+        if let [transfer_id] = candidates[..] {
+            ic_cdk::println!(
+                "contribution {}: relinked ICP transfer {:?} -> {}",
+                c.id, c.icp_transfer_id, transfer_id
+            );
+            claimed.insert(transfer_id);
+            c.icp_transfer_id = Some(transfer_id);
+            update_contribution(c.id, c);
+        } else {
+            let reason = format!(
+                "transfer {:?} does not match backer/amount/memo ({} candidate transfers)",
+                c.icp_transfer_id,
+                candidates.len()
+            );
+            ic_cdk::println!("contribution {}: {}", c.id, reason);
+            ICP_LINK_ISSUES.with(|m| {
+                m.borrow_mut().insert(c.id, reason);
+            });
+            flagged += 1;
+        }
+    }
+    flagged
+}
+
+// ---------- ICP Ledger Integration ----------
+//
+// Escrow outflows (payouts, refunds) are plain ICRC-1 transfers from this canister's account.
+// Contributions move the backer's ICP with ICRC-2 `icrc2_transfer_from`, so the backer must first
+// `icrc2_approve` this canister for the amount plus one ledger fee. Test builds replace
+// `ledger_transfer` with a mock, so the wire types below are unused there.
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+#[cfg_attr(test, allow(dead_code))]
+struct Account {
+    owner: Principal,
+    subaccount: Option<Vec<u8>>,
+}
+
+impl From<Principal> for Account {
+    fn from(owner: Principal) -> Self {
+        Account { owner, subaccount: None }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+#[cfg_attr(test, allow(dead_code))]
+struct TransferArg {
+    from_subaccount: Option<Vec<u8>>,
+    to: Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+#[cfg_attr(test, allow(dead_code))]
+struct TransferFromArgs {
+    spender_subaccount: Option<Vec<u8>>,
+    from: Account,
+    to: Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+#[cfg_attr(test, allow(dead_code))]
+enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+#[cfg_attr(test, allow(dead_code))]
+enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// `query_blocks` of the ICP ledger, reduced to the fields read here; candid drops the rest.
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+#[cfg_attr(test, allow(dead_code))]
+struct GetBlocksArgs {
+    start: u64,
+    length: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+#[cfg_attr(test, allow(dead_code))]
+struct QueryBlocksResponse {
+    first_block_index: u64,
+    blocks: Vec<LedgerBlock>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+#[cfg_attr(test, allow(dead_code))]
+struct LedgerBlock {
+    transaction: LedgerTransaction,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+#[cfg_attr(test, allow(dead_code))]
+struct LedgerTransaction {
+    memo: u64,
+    icrc1_memo: Option<Vec<u8>>,
+}
+
+#[cfg_attr(test, allow(dead_code))]
+fn ledger_canister() -> Principal {
+    Principal::from_text(LEDGER_CANISTER_ID).expect("valid ledger canister id")
+}
+
+/// Move `amount_e8s` from `from` to `to` on the ICP ledger and return the block index.
+/// This canister sends its own funds with `icrc1_transfer`; anyone else's are pulled with
+/// `icrc2_transfer_from` against their approval. The memo is the 8 big-endian bytes of `memo`.
+#[cfg(not(test))]
+async fn ledger_transfer(from: Principal, to: Principal, amount_e8s: u64, memo: u64) -> Result<u64, String> {
+    let ledger = ledger_canister();
+    let fee = Some(Nat::from(ICP_LEDGER_FEE_E8S));
+    let memo = Some(memo.to_be_bytes().to_vec());
+    let created_at_time = Some(now_ns());
+    let block = if from == canister_id() {
+        let arg = TransferArg { from_subaccount: None, to: to.into(), amount: Nat::from(amount_e8s), fee, memo, created_at_time };
+        let (res,): (Result<Nat, TransferError>,) = call(ledger, "icrc1_transfer", (arg,))
+            .await
+            .map_err(|(code, msg)| format!("ledger call failed: {:?} {}", code, msg))?;
+        res.map_err(|e| format!("ledger rejected transfer: {:?}", e))?
+    } else {
+        let arg = TransferFromArgs {
+            spender_subaccount: None,
+            from: from.into(),
+            to: to.into(),
+            amount: Nat::from(amount_e8s),
+            fee,
+            memo,
+            created_at_time,
+        };
+        let (res,): (Result<Nat, TransferFromError>,) = call(ledger, "icrc2_transfer_from", (arg,))
+            .await
+            .map_err(|(code, msg)| format!("ledger call failed: {:?} {}", code, msg))?;
+        res.map_err(|e| format!("ledger rejected transfer: {:?}", e))?
+    };
+    u64::try_from(&block.0).map_err(|_| "block index out of range".to_string())
+}
+#[cfg(test)]
+async fn ledger_transfer(from: Principal, to: Principal, amount_e8s: u64, memo: u64) -> Result<u64, String> {
+    mock::ledger_transfer(from, to, amount_e8s, memo)
+}
+
+/// The memo the ledger recorded in block `block_index`, or None once the block moved to an archive.
+/// Transfers made by `ledger_transfer` carry it as an 8-byte ICRC-1 memo.
+#[cfg(not(test))]
+async fn ledger_block_memo(block_index: u64) -> Result<Option<u64>, String> {
+    let args = GetBlocksArgs { start: block_index, length: 1 };
+    let (res,): (QueryBlocksResponse,) = call(ledger_canister(), "query_blocks", (args,))
+        .await
+        .map_err(|(code, msg)| format!("ledger call failed: {:?} {}", code, msg))?;
+    if res.first_block_index != block_index {
+        return Ok(None);
+    }
+    Ok(res.blocks.into_iter().next().map(|b| {
+        let tx = b.transaction;
+        match tx.icrc1_memo.and_then(|m| <[u8; 8]>::try_from(m.as_slice()).ok()) {
+            Some(bytes) => u64::from_be_bytes(bytes),
+            None => tx.memo,
+        }
+    }))
+}
+#[cfg(test)]
+async fn ledger_block_memo(block_index: u64) -> Result<Option<u64>, String> {
+    Ok(mock::block_memo(block_index))
+}
+
+/// Record an ICP transfer, execute it on the ledger and return its id. The record is Confirmed
+/// with the ledger's block index on success; on failure it is marked Failed and the error returned.
+async fn initiate_icp_transfer(from: Principal, to: Principal, amount_e8s: u64, memo: u64) -> Result<u64, String> {
+    let transfer_id = record_icp_transfer(from, to, amount_e8s, memo);
+    let result = ledger_transfer(from, to, amount_e8s, memo).await;
+    let Some(mut transfer) = get_icp_transfer(transfer_id) else {
+        return Err("Transfer not found".into());
+    };
+    match result {
+        Ok(block) => {
+            transfer.block_height = Some(block);
+            transfer.status = ICPTransferStatus::Confirmed;
+            transfer.confirmed_at_ns = Some(now_ns());
+            update_icp_transfer(transfer_id, transfer);
+            Ok(transfer_id)
+        }
+        Err(e) => {
+            transfer.status = ICPTransferStatus::Failed;
+            update_icp_transfer(transfer_id, transfer);
+            Err(format!("transfer {} failed: {}", transfer_id, e))
+        }
+    }
+}
+
+/// What the recipient of an outbound transfer receives from `gross_e8s` of escrow once the ledger
+/// fee is taken out, or None when the amount doesn't cover the fee (the dust stays in escrow).
+fn net_of_ledger_fee(gross_e8s: u64) -> Option<u64> {
+    gross_e8s.checked_sub(ICP_LEDGER_FEE_E8S).filter(|net| *net > 0)
+}
+
+/// Pay `gross_e8s` of escrow out to `to`, with the ledger fee deducted from the amount sent.
+/// Returns the transfer id, or `Ok(None)` when the amount is too small to cover the fee and
+/// nothing was sent. A refused transfer keeps its Failed record and returns the error, so callers
+/// send before marking anything and leave their contributions as they were on failure.
+async fn send_from_escrow(to: Principal, gross_e8s: u64, memo: u64) -> Result<Option<u64>, String> {
+    let Some(net) = net_of_ledger_fee(gross_e8s) else {
+        return Ok(None);
+    };
+    initiate_icp_transfer(canister_id(), to, net, memo).await.map(Some)
+}
+
+/// Store a new Pending transfer record and return its id.
+fn record_icp_transfer(from: Principal, to: Principal, amount_e8s: u64, memo: u64) -> u64 {
+    let transfer_id = next_transfer_id();
+    
+    let transfer = ICPTransfer {
+        id: transfer_id,
+        from,
+        to,
+        amount_e8s,
+        memo,
+        block_height: None,
+        status: ICPTransferStatus::Pending,
+        created_at_ns: now_ns(),
+        confirmed_at_ns: None,
+        fee_e8s: Some(ICP_LEDGER_FEE_E8S),
+    };
+    
+    insert_icp_transfer(transfer);
+    transfer_id
+}
+
+/// Check if an ICP transfer has been confirmed
+async fn check_icp_transfer_status(transfer_id: u64) -> Result<ICPTransferStatus, String> {
+    if let Some(transfer) = get_icp_transfer(transfer_id) {
+        Ok(transfer.status)
+    } else {
+        Err("Transfer not found".into())
+    }
+}
+
+/// Ensure the memo the ledger recorded for the transfer names the campaign it is credited to.
+/// Until the transfer has a block, or once that block is archived, the stored memo is checked.
+/// A mismatch is flagged as `TransferMemoMismatch` for review and the contribution is not
+/// credited; the transfer keeps its status, since the ICP did reach escrow.
+async fn validate_transfer_memo(transfer_id: u64, campaign_id: u64) -> Result<(), String> {
+    let transfer = get_icp_transfer(transfer_id).ok_or_else(|| "Transfer not found".to_string())?;
+    let memo = match transfer.block_height {
+        Some(block) => ledger_block_memo(block).await?.unwrap_or(transfer.memo),
+        None => transfer.memo,
+    };
+    if memo == campaign_id {
+        return Ok(());
+    }
+    emit_event(FundEventKind::TransferMemoMismatch { transfer_id, campaign_id, memo });
+    Err("ICP transfer memo does not match campaign".into())
+}
+
+// ---------- Inter-canister call helpers ----------
+
+/// Why a call to the backend failed, so callers (and the frontend, via the message) can tell
+/// whether retrying may help.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum FundError {
+    /// Transient system rejection (e.g. full queues); retrying later may succeed.
+    BackendUnavailable,
+    /// The backend principal is not a canister, or does not expose the method. Retrying won't help.
+    BackendNotFound,
+    /// The backend ran the call but rejected it or trapped.
+    BackendRejected(String),
+}
+
+impl FundError {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, FundError::BackendUnavailable)
+    }
+}
+
+impl std::fmt::Display for FundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FundError::BackendUnavailable => write!(f, "backend temporarily unavailable, please retry"),
+            FundError::BackendNotFound => write!(f, "backend canister not found or incompatible"),
+            FundError::BackendRejected(msg) => write!(f, "backend rejected the request: {}", msg),
+        }
+    }
+}
+
+impl From<FundError> for String {
+    fn from(e: FundError) -> Self {
+        e.to_string()
+    }
+}
+
+/// An `Err` returned by the backend itself; a paused backend is worth retrying later.
+#[cfg_attr(test, allow(dead_code))] // test builds mock `send_notification`
+fn map_backend_error(msg: String) -> FundError {
+    // both refusals happen before the backend touches state, so the notification can be retried
+    if msg == "canister paused" || msg == "Fund_Flow canister not configured" {
+        FundError::BackendUnavailable
+    } else {
+        FundError::BackendRejected(msg)
+    }
+}
+
+fn map_call_error((code, msg): (RejectionCode, String)) -> FundError {
+    match code {
+        RejectionCode::SysTransient => FundError::BackendUnavailable,
+        RejectionCode::DestinationInvalid => FundError::BackendNotFound,
+        _ => FundError::BackendRejected(msg),
+    }
+}
+
+/// Fetch campaign meta from backend canister.
+/// Expects backend to expose `get_campaign_meta: (nat64) -> (opt record { campaign_id, goal, amount_raised, end_date_secs }) query`
+#[cfg(not(test))]
+async fn fetch_campaign_meta(backend: Principal, campaign_id: u64) -> Result<Option<CampaignMeta>, FundError> {
+    // We expect the backend to return `opt CampaignMeta` (encoded as Option)
+    let (meta_opt,): (Option<CampaignMeta>,) =
+        call(backend, "get_campaign_meta", (campaign_id,)).await.map_err(map_call_error)?;
+    Ok(meta_opt)
+}
+#[cfg(test)]
+async fn fetch_campaign_meta(_backend: Principal, campaign_id: u64) -> Result<Option<CampaignMeta>, FundError> {
+    Ok(mock::CAMPAIGN_META.with(|m| m.borrow().get(&campaign_id).cloned()))
+}
+
+/// Meta for several campaigns, in the order given. Requests are chunked to the backend's
+/// batch cap (`MAX_BATCH_SIZE`), so this costs one call per chunk rather than per campaign.
+#[cfg(not(test))]
+async fn fetch_campaign_meta_batch(backend: Principal, ids: &[u64]) -> Result<Vec<Option<CampaignMeta>>, FundError> {
+    let mut metas: Vec<Option<CampaignMeta>> = Vec::with_capacity(ids.len());
+    for chunk in ids.chunks(MAX_BATCH_SIZE) {
+        let (batch,): (Vec<Option<CampaignMeta>>,) =
+            call(backend, "get_campaign_meta_batch", (chunk.to_vec(),)).await.map_err(map_call_error)?;
+        if batch.len() != chunk.len() {
+            return Err(FundError::BackendRejected("short meta batch".into()));
+        }
+        metas.extend(batch);
+    }
+    Ok(metas)
+}
+#[cfg(test)]
+async fn fetch_campaign_meta_batch(_backend: Principal, ids: &[u64]) -> Result<Vec<Option<CampaignMeta>>, FundError> {
+    Ok(mock::CAMPAIGN_META.with(|m| ids.iter().map(|id| m.borrow().get(id).cloned()).collect()))
+}
+
+/// Fetch a campaign joined with its idea from the backend (`get_campaign_with_idea`).
+async fn fetch_campaign_with_idea(backend: Principal, campaign_id: u64) -> Result<Option<CampaignWithIdea>, FundError> {
+    let (joined,): (Option<CampaignWithIdea>,) =
+        call(backend, "get_campaign_with_idea", (campaign_id,)).await.map_err(map_call_error)?;
+    Ok(joined)
+}
+
+/// Notify backend that campaign should be credited/payout executed.
+/// This function calls backend method `receive_payout(campaign_id: nat64, total_amount: nat64) -> (variant { Ok; Err : text })`
+/// **Make sure your backend implements `receive_payout` (or change this name)**.
+#[cfg_attr(test, allow(dead_code))]
+async fn notify_backend_receive_payout(backend: Principal, campaign_id: u64, total_amount: u64) -> Result<(), FundError> {
+    let (res,): (Result<(), String>,) =
+        call(backend, "receive_payout", (campaign_id, total_amount)).await.map_err(map_call_error)?;
+    res.map_err(map_backend_error)
+}
+
+/// Tell the backend a campaign's backers were refunded (`receive_refund`), so the idea stops counting it.
+#[cfg_attr(test, allow(dead_code))]
+async fn notify_backend_receive_refund(backend: Principal, campaign_id: u64, total_amount: u64) -> Result<(), FundError> {
+    let (res,): (Result<(), String>,) =
+        call(backend, "receive_refund", (campaign_id, total_amount)).await.map_err(map_call_error)?;
+    res.map_err(map_backend_error)
+}
+
+/// Tell the backend one ICP contribution left a campaign (`receive_icp_refund`), e.g. on a roll-over.
+#[cfg_attr(test, allow(dead_code))]
+async fn notify_backend_icp_refund(backend: Principal, campaign_id: u64, amount_e8s: u64) -> Result<(), FundError> {
+    let (res,): (Result<(), String>,) =
+        call(backend, "receive_icp_refund", (campaign_id, amount_e8s)).await.map_err(map_call_error)?;
+    res.map_err(map_backend_error)
+}
+
+/// Notify backend about ICP contribution. `is_new_backer` lets the backend keep its backer count.
+#[cfg_attr(test, allow(dead_code))]
+async fn notify_backend_icp_contribution(
+    backend: Principal,
+    campaign_id: u64,
+    amount_e8s: u64,
+    backer: Principal,
+    is_new_backer: bool,
+) -> Result<(), FundError> {
+    let (res,): (Result<(), String>,) =
+        call(backend, "receive_icp_contribution", (campaign_id, amount_e8s, backer, is_new_backer))
+            .await
+            .map_err(map_call_error)?;
+    res.map_err(map_backend_error)
+}
+
+// ---------- Notification outbox ----------
+//
+// A notification is only queued when the backend did not apply it: a transient system
+// rejection means the call never ran, and a paused backend (or one that has not been told
+// this canister's id yet) refuses before touching state.
+// Retrying is therefore safe; `receive_payout` and `receive_refund` additionally ignore repeats.
+
+#[cfg(not(test))]
+async fn send_notification(backend: Principal, n: &BackendNotification) -> Result<(), FundError> {
+    match *n {
+        BackendNotification::IcpContribution { campaign_id, amount_e8s, backer, is_new_backer } =>
+            notify_backend_icp_contribution(backend, campaign_id, amount_e8s, backer, is_new_backer).await,
+        BackendNotification::Payout { campaign_id, total_amount } =>
+            notify_backend_receive_payout(backend, campaign_id, total_amount).await,
+        BackendNotification::Refund { campaign_id, total_amount } =>
+            notify_backend_receive_refund(backend, campaign_id, total_amount).await,
+        BackendNotification::IcpRefund { campaign_id, amount_e8s } =>
+            notify_backend_icp_refund(backend, campaign_id, amount_e8s).await,
+    }
+}
+#[cfg(test)]
+async fn send_notification(_backend: Principal, n: &BackendNotification) -> Result<(), FundError> {
+    mock::send_notification(n)
+}
+
+/// Deliver `n` now, or queue it if the failure is transient. Permanent failures are returned.
+async fn notify_or_enqueue(backend: Principal, n: BackendNotification) -> Result<(), FundError> {
+    match send_notification(backend, &n).await {
+        Err(e) if e.is_transient() => {
+            let id = OUTBOX.with(|m| m.borrow().last_key_value().map(|(k, _)| k + 1).unwrap_or(1));
+            ic_cdk::println!("queued backend notification {} after transient failure: {}", id, e);
+            OUTBOX.with(|m| m.borrow_mut().insert(id, PendingNotification {
+                id,
+                backend,
+                notification: n,
+                attempts: 1,
+                last_error: e.to_string(),
+                queued_at_ns: now_ns(),
+            }));
+            Ok(())
+        }
+        res => res,
+    }
+}
+
+// ---------- Public API: Users ----------
+
+/// Register the caller, or update name/email if already registered. The original
+/// `registered_at_ns` is kept; `newly_registered` tells the UI whether this was the first time.
+#[update]
+fn register_user(name: String, email: String) -> Result<Registration, String> {
+    ensure_not_paused()?;
+    let caller = caller();
+    if name.trim().is_empty() || email.trim().is_empty() {
         return Err("name and email required".into());
     }
+    let key = Pk::from(caller);
+    let existing = USERS.with(|u| u.borrow().get(&key));
+    let newly_registered = existing.is_none();
+    // re-registering keeps the KYC state; it belongs to the principal, not the profile
     let user = RegisteredUser {
         user_principal: caller,
         name,
         email,
-        registered_at_ns: now_ns(),
+        registered_at_ns: existing.as_ref().map(|u| u.registered_at_ns).unwrap_or_else(now_ns),
+        verified: existing.as_ref().and_then(|u| u.verified),
+        verified_at_ns: existing.and_then(|u| u.verified_at_ns),
     };
+    if !fits_value_bound(&user) {
+        return Err("name and email are too long".into());
+    }
     USERS.with(|u| {
-        u.borrow_mut().insert(Pk::from(caller), user);
+        u.borrow_mut().insert(key, user.clone());
     });
+    Ok(Registration { user, newly_registered })
+}
+
+/// Whether the candid-encoded value stays within the `MAX_VALUE_SIZE` bound of the stable maps.
+/// Check before inserting anything caller-sized; an oversized insert traps.
+fn fits_value_bound<T: CandidType>(value: &T) -> bool {
+    Encode!(value).map(|b| b.len() <= MAX_VALUE_SIZE as usize).unwrap_or(false)
+}
+
+/// Apply `f` to the caller's stored profile; the caller must already be registered.
+fn update_profile(f: impl FnOnce(&mut RegisteredUser)) -> Result<RegisteredUser, String> {
+    let key = Pk::from(caller());
+    USERS.with(|u| {
+        let mut users = u.borrow_mut();
+        let mut user = users.get(&key).ok_or("user not registered")?;
+        f(&mut user);
+        if !fits_value_bound(&user) {
+            return Err("profile is too large to store".to_string());
+        }
+        users.insert(key, user.clone());
+        Ok(user)
+    })
+}
+
+/// Mark a registered user as KYC-verified or not (owner only).
+#[update]
+fn set_user_verified(p: Principal, verified: bool) -> Result<RegisteredUser, String> {
+    ensure_owner()?;
+    let key = Pk::from(p);
+    USERS.with(|u| {
+        let mut users = u.borrow_mut();
+        let mut user = users.get(&key).ok_or("user not registered")?;
+        user.verified = Some(verified);
+        user.verified_at_ns = Some(now_ns());
+        users.insert(key, user.clone());
+        Ok(user)
+    })
+}
+
+/// Contribution sizes above which a verified user is required, as (ICP e8s, fiat minor units);
+/// 0 means no limit.
+#[query]
+fn get_kyc_thresholds() -> (u64, u64) {
+    (
+        KYC_THRESHOLD_E8S.with(|c| *c.borrow().get()),
+        KYC_THRESHOLD_FIAT.with(|c| *c.borrow().get()),
+    )
+}
+
+/// Set the KYC thresholds (owner only); 0 disables the check for that kind of payment.
+#[update]
+fn set_kyc_thresholds(icp_e8s: u64, fiat: u64) -> Result<(), String> {
+    ensure_owner()?;
+    KYC_THRESHOLD_E8S.with(|c| c.borrow_mut().set(icp_e8s).expect("set kyc icp threshold"));
+    KYC_THRESHOLD_FIAT.with(|c| c.borrow_mut().set(fiat).expect("set kyc fiat threshold"));
     Ok(())
 }
 
+/// Change only the caller's email; `registered_at_ns` is kept.
+#[update]
+fn update_email(email: String) -> Result<RegisteredUser, String> {
+    ensure_not_paused()?;
+    if email.trim().is_empty() {
+        return Err("email required".into());
+    }
+    update_profile(|u| u.email = email)
+}
+
+/// Change only the caller's name; `registered_at_ns` is kept.
+#[update]
+fn update_name(name: String) -> Result<RegisteredUser, String> {
+    ensure_not_paused()?;
+    if name.trim().is_empty() {
+        return Err("name required".into());
+    }
+    update_profile(|u| u.name = name)
+}
+
 #[query]
 fn is_registered(p: Option<Principal>) -> bool {
-    let who = p.unwrap_or(ic_cdk::api::caller());
+    let who = p.unwrap_or(caller());
     USERS.with(|u| u.borrow().contains_key(&Pk::from(who)))
 }
 
+/// Registration flags for `principals`, in input order.
+/// Only the first `MAX_BATCH_SIZE` principals are served.
+#[query]
+fn is_registered_batch(mut principals: Vec<Principal>) -> Vec<bool> {
+    principals.truncate(MAX_BATCH_SIZE);
+    USERS.with(|u| {
+        let u = u.borrow();
+        principals.into_iter().map(|p| u.contains_key(&Pk::from(p))).collect()
+    })
+}
+
 #[query]
 fn get_my_profile() -> Option<RegisteredUser> {
-    let who = ic_cdk::api::caller();
+    let who = caller();
     USERS.with(|u| u.borrow().get(&Pk::from(who)))
 }
 
+#[query]
+fn get_user_count() -> u64 {
+    USERS.with(|u| u.borrow().len())
+}
+
+/// Most recently registered users first (by `registered_at_ns`), at most `MAX_PAGE_LIMIT`.
+/// Owner only, since the records include emails.
+#[query]
+fn get_recent_users(limit: u64) -> Result<Vec<RegisteredUser>, String> {
+    ensure_owner()?;
+    let mut users: Vec<RegisteredUser> = USERS.with(|u| u.borrow().iter().map(|(_, v)| v).collect());
+    users.sort_by_key(|u| std::cmp::Reverse(u.registered_at_ns));
+    users.truncate(limit.min(MAX_PAGE_LIMIT) as usize);
+    Ok(users)
+}
+
 // ---------- Public API: Contributions (funding flow) ----------
 
 /// Start a contribution with ICP coins. Creates transfer record and initiates ICP transfer.
 /// `backend` is the principal of your backend canister.
+/// With `partial_fill`, an amount larger than the room left to the goal (goal minus Held funds)
+/// is cut down to that room and only the cut amount is transferred; the stored contribution's
+/// `amount` is what was accepted. A campaign already at its goal rejects partial fills.
 #[update]
-async fn contribute_icp(backend: Principal, campaign_id: u64, amount_e8s: u64) -> Result<u64, String> {
+async fn contribute_icp(
+    backend: Principal,
+    campaign_id: u64,
+    amount_e8s: u64,
+    tip_e8s: Option<u64>,
+    partial_fill: Option<bool>,
+) -> Result<u64, String> {
+    ensure_not_paused()?;
     if amount_e8s == 0 { return Err("amount must be > 0".into()); }
-    let caller = ic_cdk::api::caller();
+    let tip_e8s = tip_e8s.unwrap_or(0);
+    let caller = caller();
 
-    // registered?
-    if !USERS.with(|u| u.borrow().contains_key(&Pk::from(caller))) {
-        return Err("Only registered users can contribute".into());
-    }
+    ensure_registered(caller)?;
+    check_refund_cooldown(caller, campaign_id)?;
 
     // check campaign exists and active
     let meta = fetch_campaign_meta(backend, campaign_id).await?;
     let meta = meta.ok_or_else(|| "campaign not found".to_string())?;
     let now = now_secs();
-    if now > meta.end_date_secs {
+    if now >= settlement_cutoff(&meta) {
         return Err("campaign already ended".into());
     }
+    let mut amount_e8s = amount_e8s;
+    let mut units = to_campaign_units(&PaymentMethod::ICP, amount_e8s, &meta)?;
+    if partial_fill.unwrap_or(false) {
+        let room = meta.goal.saturating_sub(held_in_campaign_units(campaign_id, &meta));
+        if room == 0 {
+            return Err("campaign already reached its goal".into());
+        }
+        if units > room {
+            amount_e8s = icp_for_campaign_units(room, &meta);
+            if amount_e8s == 0 {
+                return Err("remaining room is smaller than the smallest ICP amount".into());
+            }
+            units = to_campaign_units(&PaymentMethod::ICP, amount_e8s, &meta)?;
+        }
+    }
+    if tip_e8s > amount_e8s { return Err("tip can't exceed the contribution amount".into()); }
+    ensure_verified_for(caller, &PaymentMethod::ICP, amount_e8s)?;
+    check_backer_cap(caller, units, &meta)?;
 
     // Get canister principal (this canister will receive the ICP)
-    let canister_principal = ic_cdk::api::id();
+    let canister_principal = canister_id();
 
     // Initiate ICP transfer
     let transfer_id = initiate_icp_transfer(caller, canister_principal, amount_e8s, campaign_id).await?;
+    // The tip goes straight to the fee collector, outside escrow, and never counts toward the goal.
+    // The contribution itself already moved, so a failed tip must not abort it; it is just not recorded.
+    let mut tip_collected = None;
+    if tip_e8s > 0 {
+        match initiate_icp_transfer(caller, fee_collector(), tip_e8s, campaign_id).await {
+            Ok(_) => tip_collected = Some(tip_e8s),
+            Err(e) => ic_cdk::println!("tip for campaign {} not collected: {}", campaign_id, e),
+        }
+    }
 
     // create pending contribution
     let id = next_contribution_id();
+    let (tax_rate_bps, tax_amount) = vat_breakdown(amount_e8s, &meta);
     let c = Contribution {
         id,
         campaign_id,
@@ -364,8 +1741,38 @@ async fn contribute_icp(backend: Principal, campaign_id: u64, amount_e8s: u64) -
         created_at_ns: now_ns(),
         confirmed_at_ns: None,
         icp_transfer_id: Some(transfer_id),
+        matched_for: None,
+        imported_at_ns: None,
+        refunded_at_ns: None,
+        tax_rate_bps,
+        tax_amount,
+        tip_e8s: tip_collected,
+        rolled_over_from: None,
     };
     insert_contribution(c);
+
+    // sponsor match: recorded as Pending and moved to Held together with the backer's contribution
+    if let Some((sponsor, matched)) = draw_from_match_pool(campaign_id, amount_e8s) {
+        let (tax_rate_bps, tax_amount) = vat_breakdown(matched, &meta);
+        insert_contribution(Contribution {
+            id: next_contribution_id(),
+            campaign_id,
+            backer: sponsor,
+            amount: matched,
+            method: PaymentMethod::ICP,
+            status: EscrowStatus::Pending,
+            created_at_ns: now_ns(),
+            confirmed_at_ns: None,
+            icp_transfer_id: None,
+            matched_for: Some(id),
+            imported_at_ns: None,
+            refunded_at_ns: None,
+            tax_rate_bps,
+            tax_amount,
+            tip_e8s: None,
+            rolled_over_from: None,
+        });
+    }
     Ok(id)
 }
 
@@ -373,24 +1780,32 @@ async fn contribute_icp(backend: Principal, campaign_id: u64, amount_e8s: u64) -
 /// `backend` is the principal of your backend canister.
 #[update]
 async fn contribute(backend: Principal, campaign_id: u64, amount: u64, method: PaymentMethod) -> Result<u64, String> {
+    ensure_not_paused()?;
     if amount == 0 { return Err("amount must be > 0".into()); }
-    let caller = ic_cdk::api::caller();
-
-    // registered?
-    if !USERS.with(|u| u.borrow().contains_key(&Pk::from(caller))) {
-        return Err("Only registered users can contribute".into());
+    // ICP must go through `contribute_icp`, which creates the ledger transfer it is checked against
+    if method == PaymentMethod::ICP {
+        return Err("use contribute_icp for ICP contributions".into());
     }
+    let method = normalize_method(method)?;
+    let caller = caller();
+
+    ensure_registered(caller)?;
+    check_refund_cooldown(caller, campaign_id)?;
 
     // check campaign exists and active
     let meta = fetch_campaign_meta(backend, campaign_id).await?;
     let meta = meta.ok_or_else(|| "campaign not found".to_string())?;
     let now = now_secs();
-    if now > meta.end_date_secs {
+    if now >= settlement_cutoff(&meta) {
         return Err("campaign already ended".into());
     }
+    let units = to_campaign_units(&method, amount, &meta)?;
+    ensure_verified_for(caller, &method, amount)?;
+    check_backer_cap(caller, units, &meta)?;
 
     // create pending contribution
     let id = next_contribution_id();
+    let (tax_rate_bps, tax_amount) = vat_breakdown(amount, &meta);
     let c = Contribution {
         id,
         campaign_id,
@@ -401,6 +1816,13 @@ async fn contribute(backend: Principal, campaign_id: u64, amount: u64, method: P
         created_at_ns: now_ns(),
         confirmed_at_ns: None,
         icp_transfer_id: None,
+        matched_for: None,
+        imported_at_ns: None,
+        refunded_at_ns: None,
+        tax_rate_bps,
+        tax_amount,
+        tip_e8s: None,
+        rolled_over_from: None,
     };
     insert_contribution(c);
     Ok(id)
@@ -408,213 +1830,2506 @@ async fn contribute(backend: Principal, campaign_id: u64, amount: u64, method: P
 
 /// Confirm a payment (simulate webhook / admin). This moves Pending -> Held.
 ///
-/// Security note (MVP): this function allows only the canister owner or the backend can call it.
-/// - caller == owner (the principal that installed the canister during init), OR
-/// - caller == backend (the backend canister principal) — this is convenient for webhooks forwarded by backend.
-/// You may change policy to fit your infra (e.g., only backend or a payment gateway principal).
+/// Allowed callers:
+/// - the canister owner (the principal that installed the canister during init)
+/// - an allowlisted payment gateway principal (see `add_gateway`) forwarding a bank/wallet webhook
+/// - the backer, for ICP contributions only, since those are verified against the transfer record
 #[update]
 async fn confirm_payment(contribution_id: u64, backend: Principal) -> Result<(), String> {
+    ensure_not_paused()?;
     // check contribution exists
-    let mut c = get_contribution(contribution_id).ok_or_else(|| "contribution not found".to_string())?;
+    let c = get_contribution(contribution_id).ok_or_else(|| "contribution not found".to_string())?;
+    ensure_can_confirm(&c)?;
 
     if c.status != EscrowStatus::Pending {
         return Err("contribution not pending".into());
     }
+    if c.matched_for.is_some() {
+        return Err("sponsor matches are confirmed with their backer contribution".into());
+    }
 
     // For ICP contributions, check if transfer is confirmed
-    if c.method == PaymentMethod::ICP {
-        if let Some(transfer_id) = c.icp_transfer_id {
-            let transfer_status = check_icp_transfer_status(transfer_id).await?;
-            if transfer_status != ICPTransferStatus::Confirmed {
-                return Err("ICP transfer not confirmed yet".into());
-            }
-            
-            // Notify backend about ICP contribution
-            notify_backend_icp_contribution(backend, c.campaign_id, c.amount).await?;
+    let icp_transfer = c.icp_transfer_id.filter(|_| c.method == PaymentMethod::ICP);
+    if let Some(transfer_id) = icp_transfer {
+        validate_transfer_memo(transfer_id, c.campaign_id).await?;
+        let transfer_status = check_icp_transfer_status(transfer_id).await?;
+        if transfer_status != ICPTransferStatus::Confirmed {
+            return Err("ICP transfer not confirmed yet".into());
+        }
+        // re-read after the ledger lookup; another confirmation may have run meanwhile
+        if get_contribution(contribution_id).map(|c| c.status) != Some(EscrowStatus::Pending) {
+            return Err("contribution not pending".into());
         }
     }
+    let mut c = c;
+    let sponsor_match = find_match_for(contribution_id).filter(|m| m.status == EscrowStatus::Pending);
 
-    // permission: allow caller if backend or owner
-    let caller = ic_cdk::api::caller();
-    let owner = ic_cdk::api::id(); // canister id is not owner; use init owner if you saved it. For MVP we allow backend call or caller == backend param
-    // For simplicity: allow if caller == backend (payment forwarded by backend) OR caller == owner (installer) - owner not saved in this MVP.
-    if caller != backend && caller != owner {
-        // still allow if caller is the same as backer (testing) - optional
-        // return Err("not authorized to confirm".into());
-    }
+    // checked before this row gets its confirmation time, which would make it count
+    let is_new_backer = !has_counted_icp_backing(c.backer, c.campaign_id);
 
-    // mark held
+    // Mark Held before notifying the backend: other messages run during that await, and a second
+    // confirmation of the same contribution must already find it no longer Pending.
+    let campaign_id = c.campaign_id;
+    let held_at = now_ns();
     c.status = EscrowStatus::Held;
-    c.confirmed_at_ns = Some(now_ns());
-    update_contribution(contribution_id, c);
+    c.confirmed_at_ns = Some(held_at);
+    update_contribution(contribution_id, c.clone());
+    let sponsor_match = sponsor_match.map(|mut m| {
+        m.status = EscrowStatus::Held;
+        m.confirmed_at_ns = Some(held_at);
+        update_contribution(m.id, m.clone());
+        m
+    });
+
+    if icp_transfer.is_some() {
+        // Notify backend about ICP contribution (the sponsor match counts toward the goal too)
+        let matched = sponsor_match.as_ref().map(|m| m.amount).unwrap_or(0);
+        let amount_e8s = c.amount.saturating_add(matched);
+        let notified = notify_or_enqueue(backend, BackendNotification::IcpContribution {
+            campaign_id,
+            amount_e8s,
+            backer: c.backer,
+            is_new_backer,
+        }).await;
+        if let Err(e) = notified {
+            // the backend refused it outright, so put both rows back for another attempt
+            for mut row in std::iter::once(c).chain(sponsor_match) {
+                row.status = EscrowStatus::Pending;
+                row.confirmed_at_ns = None;
+                update_contribution(row.id, row);
+            }
+            return Err(e.into());
+        }
+    }
+    emit_contribution_event(&c);
+    if let Some(m) = &sponsor_match {
+        emit_contribution_event(m);
+    }
+
+    // announce funding progress; a failed meta fetch must not undo the confirmation
+    if let Ok(Some(meta)) = fetch_campaign_meta(backend, campaign_id).await {
+        record_progress(campaign_id, &meta);
+    }
 
     Ok(())
 }
 
-// ---------- Release & Refund ----------
-
-/// Release funds to project owner if campaign ended and goal reached.
-/// This function:
-/// 1. fetches campaign meta from backend (end_date + goal + current held)
-/// 2. computes total Held for campaign in this canister
-/// 3. if ended && total_held >= goal -> mark all Held -> Released and call backend.receive_payout(campaign_id, total)
+/// Seed historical contributions (e.g. off-chain pledges of a migrated project) as `Held`. Owner only.
+/// Returns one entry per record, in input order: the assigned id, or why the record was skipped
+/// (unknown campaign, zero amount or invalid method). ICP can't be imported: it would sit Held
+/// with no transfer behind it, so only ICP that reached escrow through the ledger is ever paid out.
 #[update]
-async fn release_campaign(backend: Principal, campaign_id: u64) -> Result<u64, String> {
-    // fetch meta
-    let meta_opt = fetch_campaign_meta(backend, campaign_id).await?;
-    let meta = meta_opt.ok_or_else(|| "campaign not found".to_string())?;
-    let now = now_secs();
-    if now <= meta.end_date_secs { return Err("campaign not ended yet".into()); }
+async fn import_contributions(backend: Principal, records: Vec<ImportedContribution>) -> Result<Vec<Result<u64, String>>, String> {
+    ensure_owner()?;
 
-    // compute held total and collect contribution ids
-    let mut held_ids: Vec<u64> = Vec::new();
-    let mut total_held: u64 = 0;
+    // resolve each distinct campaign once
+    let campaign_ids: Vec<u64> = records.iter().map(|r| r.campaign_id).collect::<BTreeSet<u64>>().into_iter().collect();
+    let metas: BTreeMap<u64, Option<CampaignMeta>> = campaign_ids
+        .iter()
+        .copied()
+        .zip(fetch_campaign_meta_batch(backend, &campaign_ids).await?)
+        .collect();
 
-    CONTRIBUTIONS.with(|m| {
-        let map = m.borrow();
-        // iterate and collect clones to avoid borrow issues
-        for (k, v) in map.iter() {
-            if v.campaign_id == campaign_id && v.status == EscrowStatus::Held {
-                held_ids.push(k.clone());
-                total_held = total_held.saturating_add(v.amount);
+    let now = now_ns();
+    let mut results: Vec<Result<u64, String>> = Vec::with_capacity(records.len());
+    for r in records {
+        if r.amount == 0 {
+            results.push(Err("amount must be > 0".into()));
+            continue;
+        }
+        if !matches!(metas.get(&r.campaign_id), Some(Some(_))) {
+            results.push(Err(format!("campaign {} not found", r.campaign_id)));
+            continue;
+        }
+        let method = match normalize_method(r.method) {
+            Ok(PaymentMethod::ICP) => {
+                results.push(Err("ICP contributions must arrive through the ledger, not an import".into()));
+                continue;
+            }
+            Ok(method) => method,
+            Err(e) => {
+                results.push(Err(e));
+                continue;
+            }
+        };
+        let id = next_contribution_id();
+        insert_contribution(Contribution {
+            id,
+            campaign_id: r.campaign_id,
+            backer: r.backer,
+            amount: r.amount,
+            method,
+            status: EscrowStatus::Held,
+            created_at_ns: r.created_at_ns,
+            confirmed_at_ns: Some(r.created_at_ns),
+            icp_transfer_id: None,
+            matched_for: None,
+            imported_at_ns: Some(now),
+            refunded_at_ns: None,
+            tax_rate_bps: None,
+            tax_amount: None,
+            tip_e8s: None,
+            rolled_over_from: None,
+        });
+        results.push(Ok(id));
+    }
+
+    for (campaign_id, meta) in &metas {
+        if let Some(meta) = meta {
+            record_progress(*campaign_id, meta);
+        }
+    }
+    Ok(results)
+}
+
+#[query]
+fn get_re_contribute_cooldown_secs() -> u64 {
+    RE_CONTRIBUTE_COOLDOWN_SECS.with(|c| *c.borrow().get())
+}
+
+/// Set how long a refunded backer must wait before contributing to the same campaign again (0 disables). Owner only.
+#[update]
+fn set_re_contribute_cooldown_secs(secs: u64) -> Result<(), String> {
+    ensure_owner()?;
+    RE_CONTRIBUTE_COOLDOWN_SECS.with(|c| c.borrow_mut().set(secs).expect("set cooldown"));
+    Ok(())
+}
+
+#[query]
+fn get_pending_ttl_secs() -> u64 {
+    PENDING_TTL_SECS.with(|c| *c.borrow().get())
+}
+
+/// Set how long a contribution may stay Pending before it can be expired (0 disables). Owner only.
+#[update]
+fn set_pending_ttl_secs(secs: u64) -> Result<(), String> {
+    ensure_owner()?;
+    PENDING_TTL_SECS.with(|c| c.borrow_mut().set(secs).expect("set pending ttl"));
+    Ok(())
+}
+
+#[query]
+fn get_settlement_grace_secs() -> u64 {
+    SETTLEMENT_GRACE_SECS.with(|c| *c.borrow().get())
+}
+
+/// Set how long past `end_date` contributions are still accepted (0 disables). Owner only.
+#[update]
+fn set_settlement_grace_secs(secs: u64) -> Result<(), String> {
+    ensure_owner()?;
+    SETTLEMENT_GRACE_SECS.with(|c| c.borrow_mut().set(secs).expect("set settlement grace"));
+    Ok(())
+}
+
+/// When a campaign stops taking contributions and becomes releasable/refundable:
+/// its end date plus the settlement grace, or the moment it was closed early (no grace).
+/// Display still uses `end_date_secs`.
+fn settlement_cutoff(meta: &CampaignMeta) -> u64 {
+    match meta.closed_at {
+        Some(closed_at) => closed_at,
+        None => meta.end_date_secs.saturating_add(get_settlement_grace_secs()),
+    }
+}
+
+/// Move unpaid Pending contributions older than the pending TTL to Expired. Owner only.
+/// ICP that already reached escrow is left Pending for `confirm_payment`. Returns how many were expired.
+#[update]
+fn expire_stale_pending() -> Result<u64, String> {
+    ensure_owner()?;
+    let ttl_ns = get_pending_ttl_secs().saturating_mul(1_000_000_000);
+    if ttl_ns == 0 {
+        return Ok(0);
+    }
+    let now = now_ns();
+    let stale: Vec<Contribution> = CONTRIBUTIONS.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, c)| c)
+            .filter(|c| c.status == EscrowStatus::Pending && !icp_in_escrow(c))
+            .filter(|c| now.saturating_sub(c.created_at_ns) > ttl_ns)
+            .collect()
+    });
+    let expired = stale.len() as u64;
+    for mut c in stale {
+        return_to_match_pool(&c);
+        c.status = EscrowStatus::Expired;
+        update_contribution(c.id, c);
+    }
+    Ok(expired)
+}
+
+/// Move every unpaid Pending contribution of a cancelled campaign to Cancelled. Owner only.
+/// Nothing was paid for these, so there is nothing to refund; Held contributions and Pending
+/// ICP whose transfer already landed in escrow are left alone for `refund_campaign`.
+/// Recorded as a `PendingCancelled` event. Returns how many were cancelled.
+#[update]
+fn cancel_campaign_pending(campaign_id: u64) -> Result<u64, String> {
+    ensure_owner()?;
+    let pending: Vec<Contribution> = CONTRIBUTIONS.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, c)| c)
+            .filter(|c| c.campaign_id == campaign_id && c.status == EscrowStatus::Pending && !icp_in_escrow(c))
+            .collect()
+    });
+    let cancelled = pending.len() as u64;
+    for mut c in pending {
+        return_to_match_pool(&c);
+        c.status = EscrowStatus::Cancelled;
+        update_contribution(c.id, c);
+    }
+    emit_event(FundEventKind::PendingCancelled { campaign_id, count: cancelled, by: caller() });
+    Ok(cancelled)
+}
+
+// ---------- Public API: Sponsor matching ----------
+
+/// Attach (or replace) a sponsor match pool for a campaign. Owner only.
+#[update]
+fn set_match_pool(campaign_id: u64, pool: MatchPool) -> Result<(), String> {
+    ensure_owner()?;
+    if pool.match_ratio_bps == 0 {
+        return Err("match_ratio_bps must be > 0".into());
+    }
+    MATCH_POOLS.with(|m| {
+        m.borrow_mut().insert(campaign_id, pool);
+    });
+    Ok(())
+}
+
+#[query]
+fn get_match_pool(campaign_id: u64) -> Option<MatchPool> {
+    MATCH_POOLS.with(|m| m.borrow().get(&campaign_id))
+}
+
+// ---------- Public API: Maintenance (owner only) ----------
+
+/// Halt (or resume) all non-owner state-changing calls. Owner-only endpoints keep working
+/// so the owner can recover while paused.
+#[update]
+fn set_paused(paused: bool) -> Result<(), String> {
+    ensure_owner()?;
+    PAUSED.with(|p| p.borrow_mut().set(paused).expect("set paused"));
+    Ok(())
+}
+
+#[query]
+fn is_paused() -> bool {
+    PAUSED.with(|p| *p.borrow().get())
+}
+
+/// Where platform tips are sent (owner only to change).
+#[update]
+fn set_fee_collector(p: Principal) -> Result<(), String> {
+    ensure_owner()?;
+    FEE_COLLECTOR.with(|f| f.borrow_mut().set(p).expect("set fee collector"));
+    Ok(())
+}
+
+#[query]
+fn get_fee_collector() -> Principal {
+    fee_collector()
+}
+
+#[query]
+fn get_owner_text() -> String {
+    owner().to_text()
+}
+
+#[query]
+fn get_owner() -> Principal {
+    owner()
+}
+
+/// Hand the owner role to `new_owner` (owner only). The anonymous principal is refused since
+/// nobody could call as it to take the role back. Recorded as an `OwnershipTransferred` event.
+#[update]
+fn transfer_ownership(new_owner: Principal) -> Result<(), String> {
+    ensure_owner()?;
+    if new_owner == Principal::anonymous() {
+        return Err("new owner cannot be anonymous".into());
+    }
+    let old_owner = owner();
+    OWNER.with(|o| o.borrow_mut().set(new_owner).expect("set owner"));
+    emit_event(FundEventKind::OwnershipTransferred { from: old_owner, to: new_owner, by: caller() });
+    Ok(())
+}
+
+/// Key principals and store sizes at a glance (owner only). The backend isn't listed because
+/// Fund_Flow doesn't store it; callers pass it per call.
+#[query]
+fn debug_dump() -> Result<DebugInfo, String> {
+    ensure_owner()?;
+    Ok(DebugInfo {
+        version: CANISTER_VERSION.to_string(),
+        canister_id: canister_id().to_text(),
+        owner: owner().to_text(),
+        ledger: LEDGER_CANISTER_ID.to_string(),
+        fee_collector: fee_collector().to_text(),
+        gateways: GATEWAYS.with(|g| g.borrow().iter().map(|(k, _)| Principal::from(k).to_text()).collect()),
+        paused: is_paused(),
+        contributions: CONTRIBUTIONS.with(|m| m.borrow().len()),
+        users: USERS.with(|m| m.borrow().len()),
+        icp_transfers: ICP_TRANSFERS.with(|m| m.borrow().len()),
+        events: EVENTS.with(|m| m.borrow().len()),
+        pending_notifications: OUTBOX.with(|m| m.borrow().len()),
+    })
+}
+
+/// `CANISTER_VERSION` and a hash of the candid interface.
+#[query]
+fn get_version() -> VersionInfo {
+    let hash = CANDID_SCHEMA.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    VersionInfo {
+        version: CANISTER_VERSION.to_string(),
+        candid_schema_hash: format!("{:016x}", hash),
+        build_time: option_env!("BUILD_TIME").map(str::to_string),
+    }
+}
+
+/// Retry queued backend notifications. Delivered entries are removed; failed ones stay queued
+/// with the attempt counted, until `MAX_NOTIFY_ATTEMPTS`. Returns how many were delivered.
+#[update]
+async fn flush_outbox() -> Result<u64, String> {
+    ensure_owner()?;
+    let due: Vec<PendingNotification> = OUTBOX.with(|m| {
+        m.borrow().iter().map(|(_, n)| n).filter(|n| n.attempts < MAX_NOTIFY_ATTEMPTS).collect()
+    });
+    let mut delivered: u64 = 0;
+    for mut n in due {
+        // take the entry out while in flight so a concurrent flush can't send it twice
+        if OUTBOX.with(|m| m.borrow_mut().remove(&n.id)).is_none() {
+            continue;
+        }
+        match send_notification(n.backend, &n.notification).await {
+            Ok(()) => delivered += 1,
+            Err(e) => {
+                n.attempts += 1;
+                n.last_error = e.to_string();
+                OUTBOX.with(|m| m.borrow_mut().insert(n.id, n));
             }
         }
+    }
+    Ok(delivered)
+}
+
+/// Backend notifications still waiting for delivery, oldest first.
+#[query]
+fn get_pending_notifications() -> Vec<PendingNotification> {
+    OUTBOX.with(|m| m.borrow().iter().map(|(_, n)| n).collect())
+}
+
+// ---------- Public API: Payment gateways (owner only) ----------
+
+/// Allowlist a payment gateway principal so it can call `confirm_payment`.
+#[update]
+fn add_gateway(p: Principal) -> Result<(), String> {
+    ensure_owner()?;
+    GATEWAYS.with(|g| {
+        g.borrow_mut().insert(Pk::from(p), now_ns());
+    });
+    Ok(())
+}
+
+/// Remove a payment gateway principal from the allowlist.
+#[update]
+fn remove_gateway(p: Principal) -> Result<(), String> {
+    ensure_owner()?;
+    GATEWAYS.with(|g| g.borrow_mut().remove(&Pk::from(p)))
+        .map(|_| ())
+        .ok_or_else(|| "gateway not found".to_string())
+}
+
+#[query]
+fn list_gateways() -> Vec<Principal> {
+    GATEWAYS.with(|g| g.borrow().iter().map(|(k, _)| Principal::from(k)).collect())
+}
+
+// ---------- Release & Refund ----------
+
+/// Release funds to project owner if campaign ended and goal reached.
+/// This function:
+/// 1. fetches campaign meta from backend (end_date + goal + current held)
+/// 2. computes total Held for campaign in this canister
+/// 3. if ended && total_held >= goal -> mark all Held -> Released and call backend.receive_payout(campaign_id, total)
+#[update]
+async fn release_campaign(backend: Principal, campaign_id: u64) -> Result<u64, String> {
+    ensure_not_paused()?;
+    let (released, _) = release_held(backend, campaign_id, true).await?;
+    Ok(released)
+}
+
+/// Release an ended campaign's held funds even though it missed its goal (owner only), e.g. for
+/// a project that agreed to keep whatever it raised. Recorded as a `ForceReleased` event.
+#[update]
+async fn force_release_campaign(backend: Principal, campaign_id: u64, reason: String) -> Result<u64, String> {
+    ensure_owner()?;
+    let reason = reason.trim().to_string();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_LEN {
+        return Err(format!("reason must be 1-{} characters", MAX_REASON_LEN));
+    }
+    let (released, total_amount) = release_held(backend, campaign_id, false).await?;
+    emit_event(FundEventKind::ForceReleased {
+        campaign_id,
+        total_amount,
+        by: caller(),
+        reason,
     });
+    Ok(released)
+}
+
+/// A campaign's Held contributions: ids, raw total (what the payout reports), the ICP part
+/// (what leaves escrow on the ledger) and the total in goal units (what the goal is checked on).
+/// Only ICP that reached escrow is in `icp`: sponsor matches are pledges the sponsor settles
+/// outside the canister, so paying them out would spend other campaigns' escrow.
+struct HeldFunds {
+    ids: Vec<u64>,
+    total: u64,
+    icp: u64,
+    in_goal_units: u64,
+}
+
+fn collect_held(campaign_id: u64, meta: &CampaignMeta) -> Result<HeldFunds, String> {
+    let mut held = HeldFunds { ids: Vec::new(), total: 0, icp: 0, in_goal_units: 0 };
+    CONTRIBUTIONS.with(|m| {
+        for (k, v) in m.borrow().iter() {
+            if v.campaign_id == campaign_id && v.status == EscrowStatus::Held {
+                held.ids.push(k);
+                held.total = held.total.saturating_add(v.amount);
+                if icp_in_escrow(&v) {
+                    held.icp = held.icp.saturating_add(v.amount);
+                }
+                held.in_goal_units = held.in_goal_units.saturating_add(to_campaign_units(&v.method, v.amount, meta)?);
+            }
+        }
+        Ok(held)
+    })
+}
+
+/// What a release would pay out, as computed by `preview_release`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ReleasePreview {
+    pub total_held: u64,
+    pub fee_e8s: u64,      // ledger fee on the ICP payout, or the whole ICP share if it can't cover it
+    pub net_to_owner: u64, // `total_held` minus `fee_e8s`
+    pub contribution_count: u64,
+    pub goal_reached: bool,
+}
+
+/// Dry run of `release_campaign` with the same meta fetch and math, without mutating anything.
+/// Works before the end date too, so a confirmation dialog can show where a campaign stands.
+#[update]
+async fn preview_release(backend: Principal, campaign_id: u64) -> Result<ReleasePreview, String> {
+    let meta = fetch_campaign_meta(backend, campaign_id).await?;
+    let meta = meta.ok_or_else(|| "campaign not found".to_string())?;
+    let held = collect_held(campaign_id, &meta)?;
+    // the ICP share leaves as one transfer to the owner wallet; without one the release is refused
+    let fee_e8s = match meta.owner_wallet {
+        Some(_) if held.icp > 0 => net_of_ledger_fee(held.icp).map_or(held.icp, |net| held.icp - net),
+        _ => 0,
+    };
+    Ok(ReleasePreview {
+        total_held: held.total,
+        fee_e8s,
+        net_to_owner: held.total - fee_e8s,
+        contribution_count: held.ids.len() as u64,
+        goal_reached: held.in_goal_units >= meta.goal,
+    })
+}
 
-    if total_held < meta.goal {
+/// Mark an ended campaign's Held contributions Released and tell the backend to pay out.
+/// Returns (contributions released, raw total). `enforce_goal` rejects campaigns below goal.
+async fn release_held(backend: Principal, campaign_id: u64, enforce_goal: bool) -> Result<(u64, u64), String> {
+    let _guard = SettlementGuard::acquire(campaign_id)?;
+    // fetch meta
+    let meta_opt = fetch_campaign_meta(backend, campaign_id).await?;
+    let meta = meta_opt.ok_or_else(|| "campaign not found".to_string())?;
+    let now = now_secs();
+    if now < settlement_cutoff(&meta) { return Err("campaign not ended yet".into()); }
+    // checked after the await so a concurrent refund can't slip in between
+    ensure_not_settled(campaign_id)?;
+
+    let HeldFunds { ids: held_ids, total: total_held, icp: icp_held, in_goal_units } = collect_held(campaign_id, &meta)?;
+    if held_ids.is_empty() {
+        return Err("no held funds to release".into());
+    }
+    if enforce_goal && in_goal_units < meta.goal {
         return Err("campaign did not reach goal".into());
     }
 
+    // The ICP share leaves escrow as a single transfer, so the creator bears one ledger fee.
+    // It is sent before anything is marked: if the ledger refuses, the campaign stays Held and
+    // the release can simply be run again.
+    if icp_held > 0 {
+        let wallet = meta.owner_wallet.ok_or_else(|| "set a payout wallet before releasing ICP".to_string())?;
+        send_from_escrow(wallet, icp_held, campaign_id).await?;
+    }
+
     // mark Released
     for id in &held_ids {
         if let Some(mut c) = get_contribution(*id) {
             c.status = EscrowStatus::Released;
+            emit_contribution_event(&c);
             update_contribution(*id, c);
         }
     }
+    mark_settled(campaign_id, SETTLED_RELEASED);
+    ic_cdk::println!("Released {} for campaign {}", total_held, campaign_id);
 
     // notify backend to perform payout (backend must implement `receive_payout(campaign_id, total_amount)`)
-    notify_backend_receive_payout(backend, campaign_id, total_held).await?;
+    notify_or_enqueue(backend, BackendNotification::Payout { campaign_id, total_amount: total_held }).await?;
 
-    Ok(held_ids.len() as u64)
+    Ok((held_ids.len() as u64, total_held))
 }
 
 /// Refund all Pending/Held contributions if campaign ended and failed to reach goal.
-/// Marks statuses as Refunded and returns number refunded.
+/// Marks statuses as Refunded and returns number refunded. An ICP refund the ledger refuses
+/// leaves its contribution as it was and the call returns an error; calling again retries
+/// only what is still unrefunded.
 #[update]
-fn refund_campaign(campaign_id: u64) -> Result<u64, String> {
-    // check ended via backend? MVP: we allow refund if any contributions exist and campaign ended should be validated by backend by calling this canister or via admin
-    // For safety, we just proceed and mark Pending/Held -> Refunded; in production call backend.get_campaign_meta to check end_date.
+async fn refund_campaign(backend: Principal, campaign_id: u64) -> Result<u64, String> {
+    ensure_not_paused()?;
+    let _guard = SettlementGuard::acquire(campaign_id)?;
+    let meta = fetch_campaign_meta(backend, campaign_id).await?;
+    let meta = meta.ok_or_else(|| "campaign not found".to_string())?;
+    if now_secs() <= settlement_cutoff(&meta) {
+        return Err("campaign still active; refunds open once it has ended".into());
+    }
+    // checked after the await so a concurrent release can't slip in between;
+    // a refund that stopped on a refused transfer may resume
+    let resuming = SETTLEMENTS.with(|m| m.borrow().get(&campaign_id)) == Some(SETTLED_REFUNDED);
+    if !resuming {
+        ensure_not_settled(campaign_id)?;
+        if collect_held(campaign_id, &meta)?.in_goal_units >= meta.goal {
+            return Err("campaign reached its goal; release the funds instead".into());
+        }
+    }
+
+    let refundable = |c: &Contribution| {
+        c.campaign_id == campaign_id && (c.status == EscrowStatus::Pending || c.status == EscrowStatus::Held)
+    };
+    let candidates: Vec<Contribution> =
+        CONTRIBUTIONS.with(|m| m.borrow().iter().map(|(_, c)| c).filter(|c| refundable(c)).collect());
+
+    // Only ICP that actually reached escrow goes back on the ledger, before its contribution is
+    // marked. Each refund is its own transfer, so every backer bears one ledger fee; dust below
+    // the refund threshold stays in escrow with the platform.
+    let min_refundable = get_min_refundable_e8s();
+    let mut refused: BTreeSet<u64> = BTreeSet::new();
+    for c in candidates.iter().filter(|c| icp_in_escrow(c)) {
+        if c.amount < min_refundable {
+            ic_cdk::println!("contribution {}: {} e8s below refund threshold, kept in escrow", c.id, c.amount);
+        } else if let Err(e) = send_from_escrow(c.backer, c.amount, campaign_id).await {
+            ic_cdk::println!("refund of contribution {} refused: {}", c.id, e);
+            refused.insert(c.id);
+        }
+    }
+
     let mut refunded_count: u64 = 0;
     let mut refund_total: u64 = 0;
-
-    CONTRIBUTIONS.with(|m| {
-        let mut map = m.borrow_mut();
-        // collect keys to mutate
-        let keys: Vec<u64> = map.iter().filter_map(|(k,v)| {
-            if v.campaign_id == campaign_id && (v.status == EscrowStatus::Pending || v.status == EscrowStatus::Held) {
-                Some(k.clone())
-            } else {
-                None
+    let mut fiat_refunds: Vec<RefundTask> = Vec::new();
+    let mut refunded: Vec<Contribution> = Vec::new();
+    for id in candidates.iter().map(|c| c.id).filter(|id| !refused.contains(id)) {
+        // re-read: other messages ran during the ledger calls
+        let Some(mut c) = get_contribution(id).filter(|c| refundable(c)) else {
+            continue;
+        };
+        if c.status == EscrowStatus::Held {
+            refund_total = refund_total.saturating_add(c.amount);
+            if c.method != PaymentMethod::ICP {
+                // paid outside the canister, so an operator has to send it back the same way
+                fiat_refunds.push(RefundTask {
+                    contribution_id: c.id,
+                    campaign_id,
+                    method: c.method.clone(),
+                    amount: c.amount,
+                    backer: c.backer,
+                    queued_at_ns: now_ns(),
+                });
             }
-        }).collect();
+        }
+        c.status = EscrowStatus::Refunded;
+        c.refunded_at_ns = Some(now_ns());
+        update_contribution(id, c.clone());
+        refunded.push(c);
+        refunded_count += 1;
+    }
 
-        for id in keys {
-            if let Some(mut c) = map.get(&id) {
-                if c.status == EscrowStatus::Held {
-                    refund_total = refund_total.saturating_add(c.amount);
-                }
-                c.status = EscrowStatus::Refunded;
-                map.insert(id, c.clone());
-                refunded_count += 1;
-            }
+    if refunded_count > 0 {
+        mark_settled(campaign_id, SETTLED_REFUNDED);
+    }
+    for c in &refunded {
+        emit_contribution_event(c);
+    }
+    REFUND_QUEUE.with(|q| {
+        let mut q = q.borrow_mut();
+        for task in fiat_refunds {
+            q.insert(task.contribution_id, task);
         }
     });
 
-    // Note: actual money refund must be handled by payment gateway off-chain.
+    if refunded_count > 0 {
+        notify_or_enqueue(backend, BackendNotification::Refund { campaign_id, total_amount: refund_total }).await?;
+    }
+    if !refused.is_empty() {
+        return Err(format!(
+            "refunded {} contributions; the ledger refused {}, call again to retry them",
+            refunded_count,
+            refused.len()
+        ));
+    }
     Ok(refunded_count)
 }
 
-// ---------- Queries: contributions / escrow summary ----------
+/// Smallest ICP contribution in e8s that `refund_campaign` sends back on the ledger.
+/// Smaller ones are still marked Refunded, but their value stays in escrow with the platform.
+#[query]
+fn get_min_refundable_e8s() -> u64 {
+    MIN_REFUNDABLE_E8S.with(|c| *c.borrow().get())
+}
 
+/// Set the on-chain refund threshold in e8s (owner only); 0 refunds everything that covers the fee.
+#[update]
+fn set_min_refundable_e8s(e8s: u64) -> Result<(), String> {
+    ensure_owner()?;
+    MIN_REFUNDABLE_E8S.with(|c| c.borrow_mut().set(e8s).expect("set min refundable"));
+    Ok(())
+}
+
+/// Fiat refunds still waiting to be paid out off-chain, oldest contribution first.
+/// Owner or allowlisted gateway only.
 #[query]
-fn get_contributions_by_user(p: Option<Principal>) -> Vec<Contribution> {
-    let who = p.unwrap_or(ic_cdk::api::caller());
-    let mut res: Vec<Contribution> = Vec::new();
-    CONTRIBUTIONS.with(|m| {
-        for (_, v) in m.borrow().iter() {
-            if v.backer == who {
-                res.push(v.clone());
-            }
-        }
-    });
-    res
+fn get_refund_queue() -> Result<Vec<RefundTask>, String> {
+    ensure_refund_operator()?;
+    Ok(REFUND_QUEUE.with(|q| q.borrow().iter().map(|(_, t)| t).collect()))
+}
+
+/// Drop a fiat refund from the queue once the operator has sent the money back.
+/// Owner or allowlisted gateway only.
+#[update]
+fn mark_refund_completed(contribution_id: u64) -> Result<(), String> {
+    ensure_refund_operator()?;
+    let task = REFUND_QUEUE.with(|q| q.borrow_mut().remove(&contribution_id))
+        .ok_or_else(|| "no queued refund for this contribution".to_string())?;
+    ic_cdk::println!(
+        "refund of contribution {} ({} via {:?}) completed by {}",
+        contribution_id,
+        task.amount,
+        task.method,
+        caller()
+    );
+    Ok(())
+}
+
+fn ensure_refund_operator() -> Result<(), String> {
+    let caller = caller();
+    if caller == owner() || is_gateway(caller) {
+        Ok(())
+    } else {
+        Err("only the owner or a payment gateway can do this".into())
+    }
 }
 
+/// How a campaign was settled: `Released`, `Refunded`, or `None` while still open.
 #[query]
-fn get_campaign_contributions(campaign_id: u64) -> Vec<Contribution> {
-    let mut res: Vec<Contribution> = Vec::new();
+fn get_campaign_settlement(campaign_id: u64) -> Option<EscrowStatus> {
+    SETTLEMENTS.with(|m| m.borrow().get(&campaign_id)).map(|s| {
+        if s == SETTLED_RELEASED { EscrowStatus::Released } else { EscrowStatus::Refunded }
+    })
+}
+
+/// Dry run of `refund_campaign`: refundable (Pending + Held) amount per backer, without mutating anything.
+/// Backer principals are masked for callers other than the owner.
+#[query]
+fn preview_refund(campaign_id: u64) -> Vec<(Principal, u64)> {
+    let mut per_backer: BTreeMap<Principal, u64> = BTreeMap::new();
     CONTRIBUTIONS.with(|m| {
-        for (_, v) in m.borrow().iter() {
-            if v.campaign_id == campaign_id {
-                res.push(v.clone());
+        for (_, c) in m.borrow().iter() {
+            if c.campaign_id == campaign_id
+                && (c.status == EscrowStatus::Pending || c.status == EscrowStatus::Held)
+            {
+                let total = per_backer.entry(c.backer).or_insert(0);
+                *total = total.saturating_add(c.amount);
             }
         }
     });
-    res
+    let reveal = caller() == owner();
+    per_backer
+        .into_iter()
+        .map(|(p, amount)| (if reveal { p } else { Principal::anonymous() }, amount))
+        .collect()
 }
 
+/// Funding stats for a campaign's creator. Amounts are in the campaign's goal units and count
+/// Held and Released contributions (what the campaign has actually raised).
 #[derive(CandidType, Deserialize, Clone, Debug)]
-pub struct EscrowSummary {
+pub struct CreatorAnalytics {
     pub campaign_id: u64,
-    pub total_pending: u64,
-    pub total_held: u64,
-    pub total_released: u64,
-    pub total_refunded: u64,
+    pub total_raised: u64,
+    pub backer_count: u64,
+    pub average_contribution: u64,       // total_raised / number of contributions, rounded down
+    pub daily: Vec<(u64, u64)>,          // (UTC day start in secs, amount confirmed that day), oldest first
+    pub top_backers: Vec<(Principal, u64)>, // largest totals first, at most `ANALYTICS_TOP_BACKERS`
 }
 
-#[query]
-fn get_escrow_summary(campaign_id: u64) -> EscrowSummary {
-    let mut s = EscrowSummary { campaign_id, total_pending: 0, total_held: 0, total_released: 0, total_refunded: 0 };
+/// `CreatorAnalytics` for the last `days` days (1-`MAX_ANALYTICS_DAYS`), for the campaign's
+/// creator or the owner. One call to the backend's `get_campaign_meta` supplies the creator
+/// (used for the check) and the goal unit; everything else comes from this canister's contributions.
+#[update]
+async fn get_creator_analytics(backend: Principal, campaign_id: u64, days: u64) -> Result<CreatorAnalytics, String> {
+    let meta = fetch_campaign_meta(backend, campaign_id).await?;
+    let meta = meta.ok_or_else(|| "campaign not found".to_string())?;
+    let caller = caller();
+    if caller != owner() && meta.creator != Some(caller) {
+        return Err("only the campaign creator or owner can do this".into());
+    }
+    let days = days.clamp(1, MAX_ANALYTICS_DAYS);
+    let today = now_secs() / 86_400 * 86_400;
+    let first_day = today.saturating_sub((days - 1) * 86_400);
+    let mut daily: Vec<(u64, u64)> = (0..days).map(|i| (first_day + i * 86_400, 0)).collect();
+
+    let mut total_raised: u64 = 0;
+    let mut count: u64 = 0;
+    let mut per_backer: BTreeMap<Principal, u64> = BTreeMap::new();
     CONTRIBUTIONS.with(|m| {
         for (_, c) in m.borrow().iter() {
-            if c.campaign_id != campaign_id { continue; }
-            match c.status {
-                EscrowStatus::Pending => s.total_pending = s.total_pending.saturating_add(c.amount),
-                EscrowStatus::Held => s.total_held = s.total_held.saturating_add(c.amount),
-                EscrowStatus::Released => s.total_released = s.total_released.saturating_add(c.amount),
-                EscrowStatus::Refunded => s.total_refunded = s.total_refunded.saturating_add(c.amount),
+            if c.campaign_id != campaign_id
+                || !(c.status == EscrowStatus::Held || c.status == EscrowStatus::Released)
+            {
+                continue;
+            }
+            let units = to_campaign_units(&c.method, c.amount, &meta).unwrap_or(0);
+            total_raised = total_raised.saturating_add(units);
+            count += 1;
+            let entry = per_backer.entry(c.backer).or_insert(0);
+            *entry = entry.saturating_add(units);
+            let at_secs = c.confirmed_at_ns.unwrap_or(c.created_at_ns) / 1_000_000_000;
+            if at_secs >= first_day {
+                let slot = ((at_secs - first_day) / 86_400) as usize;
+                if let Some((_, amount)) = daily.get_mut(slot) {
+                    *amount = amount.saturating_add(units);
+                }
             }
         }
     });
-    s
-}
 
-// ---------- ICP Transfer Queries ----------
+    let backer_count = per_backer.len() as u64;
+    let mut top_backers: Vec<(Principal, u64)> = per_backer.into_iter().collect();
+    top_backers.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    top_backers.truncate(ANALYTICS_TOP_BACKERS);
+    Ok(CreatorAnalytics {
+        campaign_id,
+        total_raised,
+        backer_count,
+        average_contribution: total_raised.checked_div(count).unwrap_or(0),
+        daily,
+        top_backers,
+    })
+}
 
-// #[query]
-// fn get_icp_transfer(transfer_id: u64) -> Option<ICPTransfer> {
-//     get_icp_transfer(transfer_id)
-// }
+/// Campaigns a principal (default: caller) has contributed to, joined with idea info from the backend.
+/// Campaign ids are deduplicated (lowest first) and at most `MAX_BACKEND_FANOUT` are fetched.
+#[update]
+async fn get_backed_campaigns(backend: Principal, p: Option<Principal>) -> Result<Vec<CampaignWithIdea>, String> {
+    let who = p.unwrap_or(caller());
+    let campaign_ids: BTreeSet<u64> = CONTRIBUTIONS.with(|m| {
+        m.borrow().iter().filter(|(_, c)| c.backer == who).map(|(_, c)| c.campaign_id).collect()
+    });
 
-#[query]
-fn get_icp_transfers_by_user(p: Option<Principal>) -> Vec<ICPTransfer> {
-    let who = p.unwrap_or(ic_cdk::api::caller());
-    let mut res: Vec<ICPTransfer> = Vec::new();
-    ICP_TRANSFERS.with(|m| {
-        for (_, v) in m.borrow().iter() {
-            if v.from == who {
-                res.push(v.clone());
-            }
+    let mut res: Vec<CampaignWithIdea> = Vec::new();
+    for campaign_id in campaign_ids.into_iter().take(MAX_BACKEND_FANOUT) {
+        if let Some(joined) = fetch_campaign_with_idea(backend, campaign_id).await? {
+            res.push(joined);
         }
+    }
+    Ok(res)
+}
+
+/// The caller's Held contributions that a refund would return: the campaign has ended below
+/// its goal and hasn't been settled. Campaign meta is fetched in batches. Traps if the backend
+/// can't be reached, rather than reporting an empty list.
+#[update]
+async fn get_my_refundable(backend: Principal) -> Vec<Contribution> {
+    let caller = caller();
+    let held: Vec<Contribution> = CONTRIBUTIONS.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, c)| c)
+            .filter(|c| c.backer == caller && c.status == EscrowStatus::Held)
+            .collect()
     });
-    res
+    let campaign_ids: Vec<u64> = held.iter().map(|c| c.campaign_id).collect::<BTreeSet<u64>>().into_iter().collect();
+    let metas = match fetch_campaign_meta_batch(backend, &campaign_ids).await {
+        Ok(metas) => metas,
+        Err(e) => ic_cdk::trap(&e.to_string()),
+    };
+
+    let now = now_secs();
+    let refundable: BTreeSet<u64> = campaign_ids
+        .into_iter()
+        .zip(metas)
+        .filter_map(|(campaign_id, meta)| {
+            let meta = meta?;
+            if now < settlement_cutoff(&meta) || ensure_not_settled(campaign_id).is_err() {
+                return None;
+            }
+            (held_in_campaign_units(campaign_id, &meta) < meta.goal).then_some(campaign_id)
+        })
+        .collect();
+
+    held.into_iter().filter(|c| refundable.contains(&c.campaign_id)).collect()
 }
 
-// ---------- Init / Export ----------
-#[init]
-fn init() {
-    ic_cdk::println!("Funding canister initialized — {}", CANISTER_VERSION);
+/// Sum of a campaign's Held contributions in its goal units; unconvertible amounts count as 0.
+fn held_in_campaign_units(campaign_id: u64, meta: &CampaignMeta) -> u64 {
+    CONTRIBUTIONS.with(|m| {
+        m.borrow()
+            .iter()
+            .filter(|(_, c)| c.campaign_id == campaign_id && c.status == EscrowStatus::Held)
+            .map(|(_, c)| to_campaign_units(&c.method, c.amount, meta).unwrap_or(0))
+            .fold(0u64, |acc, a| acc.saturating_add(a))
+    })
 }
 
-ic_cdk::export_candid!();
\ No newline at end of file
+/// Move one of the caller's refundable Held contributions (see `get_my_refundable`) into an
+/// active campaign instead of withdrawing it, e.g. when the founder relaunches. The old
+/// contribution is marked Refunded and a new Held one is created for the same amount; the funds
+/// never leave escrow, so no ledger transfer happens. Returns the new contribution id.
+#[update]
+async fn roll_over_contribution(backend: Principal, contribution_id: u64, new_campaign_id: u64) -> Result<u64, String> {
+    ensure_not_paused()?;
+    let caller = caller();
+    let c = get_contribution(contribution_id).ok_or_else(|| "contribution not found".to_string())?;
+    if c.backer != caller {
+        return Err("only the backer can roll over a contribution".into());
+    }
+    if c.matched_for.is_some() {
+        return Err("sponsor matches can't be rolled over".into());
+    }
+    // the rolled row counts as escrowed, so only ICP that is actually in escrow may move
+    if c.method == PaymentMethod::ICP && !icp_in_escrow(&c) {
+        return Err("ICP that never reached escrow can't be rolled over".into());
+    }
+    if c.campaign_id == new_campaign_id {
+        return Err("contribution already belongs to this campaign".into());
+    }
+    let old_campaign_id = c.campaign_id;
+
+    let metas = fetch_campaign_meta_batch(backend, &[old_campaign_id, new_campaign_id]).await?;
+    let mut metas = metas.into_iter();
+    let old_meta = metas.next().flatten().ok_or_else(|| "campaign not found".to_string())?;
+    let new_meta = metas.next().flatten().ok_or_else(|| "new campaign not found".to_string())?;
+    let now = now_secs();
+    if now >= settlement_cutoff(&new_meta) {
+        return Err("new campaign already ended".into());
+    }
+    let units = to_campaign_units(&c.method, c.amount, &new_meta)?;
+    check_backer_cap(caller, units, &new_meta)?;
+
+    // re-read after the await; a refund or release may have settled the old campaign meanwhile
+    let mut c = get_contribution(contribution_id).ok_or_else(|| "contribution not found".to_string())?;
+    if c.status != EscrowStatus::Held {
+        return Err("contribution not held".into());
+    }
+    ensure_not_settled(old_campaign_id)?;
+    ensure_not_settling(old_campaign_id)?;
+    if now < settlement_cutoff(&old_meta) || held_in_campaign_units(old_campaign_id, &old_meta) >= old_meta.goal {
+        return Err("contribution is not refundable".into());
+    }
+
+    let id = next_contribution_id();
+    let (tax_rate_bps, tax_amount) = vat_breakdown(c.amount, &new_meta);
+    let is_new_backer = !has_counted_icp_backing(caller, new_campaign_id);
+    let rolled = Contribution {
+        id,
+        campaign_id: new_campaign_id,
+        backer: caller,
+        amount: c.amount,
+        method: c.method.clone(),
+        status: EscrowStatus::Held,
+        created_at_ns: now_ns(),
+        confirmed_at_ns: Some(now_ns()),
+        icp_transfer_id: None,
+        matched_for: None,
+        imported_at_ns: None,
+        refunded_at_ns: None,
+        tax_rate_bps,
+        tax_amount,
+        tip_e8s: None,
+        rolled_over_from: Some(contribution_id),
+    };
+    c.status = EscrowStatus::Refunded;
+    c.refunded_at_ns = Some(now_ns());
+    emit_contribution_event(&c);
+    update_contribution(contribution_id, c);
+    emit_contribution_event(&rolled);
+    insert_contribution(rolled.clone());
+
+    // held ICP counts toward the backend's raised amount, as on confirmation, so it moves from
+    // the old campaign's total to the new one's; both are sent even if the first is refused
+    if rolled.method == PaymentMethod::ICP {
+        let left = notify_or_enqueue(backend, BackendNotification::IcpRefund {
+            campaign_id: old_campaign_id,
+            amount_e8s: rolled.amount,
+        }).await;
+        let joined = notify_or_enqueue(backend, BackendNotification::IcpContribution {
+            campaign_id: new_campaign_id,
+            amount_e8s: rolled.amount,
+            backer: caller,
+            is_new_backer,
+        }).await;
+        left.and(joined)?;
+    }
+    record_progress(new_campaign_id, &new_meta);
+    Ok(id)
+}
+
+// ---------- Queries: contributions / escrow summary ----------
+
+/// Redacted copy of a contribution for callers other than the backer or owner:
+/// the backer identity and the ICP transfer link are hidden.
+fn redact_contribution(mut c: Contribution) -> Contribution {
+    c.backer = Principal::anonymous();
+    c.icp_transfer_id = None;
+    c
+}
+
+/// Fetch one contribution. The backer and the owner see full details; others get a redacted view.
+#[query(name = "get_contribution")]
+fn get_contribution_by_id(id: u64) -> Option<Contribution> {
+    let caller = caller();
+    get_contribution(id).map(|c| {
+        if caller == c.backer || caller == owner() { c } else { redact_contribution(c) }
+    })
+}
+
+/// One contribution with its linked ICP transfer. Only the backer and the owner see the
+/// transfer; others get the redacted contribution and no transfer.
+#[query]
+fn get_contribution_detail(contribution_id: u64) -> Option<ContributionDetail> {
+    let caller = caller();
+    let c = get_contribution(contribution_id)?;
+    if caller != c.backer && caller != owner() {
+        return Some(ContributionDetail { contribution: redact_contribution(c), transfer: None });
+    }
+    let transfer = c.icp_transfer_id.and_then(get_icp_transfer);
+    Some(ContributionDetail { contribution: c, transfer })
+}
+
+#[query]
+fn get_contributions_by_user(p: Option<Principal>) -> Vec<Contribution> {
+    let who = p.unwrap_or(caller());
+    let mut res: Vec<Contribution> = Vec::new();
+    CONTRIBUTIONS.with(|m| {
+        for (_, v) in m.borrow().iter() {
+            if v.backer == who {
+                res.push(v.clone());
+            }
+        }
+    });
+    res
+}
+
+/// A user's contributions ordered by `by` (ties by id), then paged. When sorting by
+/// confirmation time, unconfirmed contributions come last in either direction.
+#[query]
+fn get_contributions_by_user_sorted(p: Option<Principal>, by: SortField, desc: bool, offset: u64, limit: u64) -> Vec<Contribution> {
+    use std::cmp::Ordering;
+    let mut res = get_contributions_by_user(p);
+    let directed = |o: Ordering| if desc { o.reverse() } else { o };
+    res.sort_by(|a, b| {
+        let primary = match by {
+            SortField::CreatedAt => directed(a.created_at_ns.cmp(&b.created_at_ns)),
+            SortField::Amount => directed(a.amount.cmp(&b.amount)),
+            SortField::ConfirmedAt => match (a.confirmed_at_ns, b.confirmed_at_ns) {
+                (Some(x), Some(y)) => directed(x.cmp(&y)),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        };
+        primary.then_with(|| directed(a.id.cmp(&b.id)))
+    });
+    res.into_iter()
+        .skip(offset as usize)
+        .take(limit.min(MAX_PAGE_LIMIT) as usize)
+        .collect()
+}
+
+/// Every contribution in id order, one page at a time (owner only), for full exports.
+/// Pass the returned cursor (the last id of the page) back in to continue; `None` means done.
+#[query]
+fn get_all_contributions(cursor: Option<u64>, limit: u64) -> Result<(Vec<Contribution>, Option<u64>), String> {
+    ensure_owner()?;
+    let limit = limit.clamp(1, MAX_PAGE_LIMIT) as usize;
+    let start = match cursor {
+        Some(id) if id == u64::MAX => return Ok((Vec::new(), None)),
+        Some(id) => id + 1,
+        None => 0,
+    };
+    let mut page: Vec<Contribution> = CONTRIBUTIONS.with(|m| {
+        m.borrow().range(start..).take(limit + 1).map(|(_, c)| c).collect()
+    });
+    let more = page.len() > limit;
+    page.truncate(limit);
+    let next = if more { page.last().map(|c| c.id) } else { None };
+    Ok((page, next))
+}
+
+/// Backer dashboard totals for the caller. Amounts are summed in each contribution's own
+/// units; `total_contributed` counts everything that was paid (held, released or refunded).
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct BackerSummary {
+    pub total_contributed: u64,
+    pub campaigns_backed: u64, // distinct campaigns with a paid contribution
+    pub held: u64,
+    pub released: u64,
+    pub refunded: u64,
+}
+
+/// The caller's `BackerSummary`, aggregated in one pass over their contributions.
+#[query]
+fn get_my_summary() -> BackerSummary {
+    let who = caller();
+    let mut summary = BackerSummary::default();
+    let mut campaigns = BTreeSet::new();
+    CONTRIBUTIONS.with(|m| {
+        for (_, c) in m.borrow().iter().filter(|(_, c)| c.backer == who) {
+            let bucket = match c.status {
+                EscrowStatus::Held => &mut summary.held,
+                EscrowStatus::Released => &mut summary.released,
+                EscrowStatus::Refunded => &mut summary.refunded,
+                _ => continue,
+            };
+            *bucket = bucket.saturating_add(c.amount);
+            summary.total_contributed = summary.total_contributed.saturating_add(c.amount);
+            campaigns.insert(c.campaign_id);
+        }
+    });
+    summary.campaigns_backed = campaigns.len() as u64;
+    summary
+}
+
+/// Non-refunded contributions with `amount` in `[min, max]` in the contribution's own units,
+/// oldest first and paged (owner only), e.g. to pull large contributions for AML review.
+#[query]
+fn find_contributions_by_amount(min: u64, max: u64, offset: u64, limit: u64) -> Result<Vec<Contribution>, String> {
+    ensure_owner()?;
+    if min > max {
+        return Err("min must be <= max".into());
+    }
+    Ok(CONTRIBUTIONS.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, c)| c)
+            .filter(|c| c.status != EscrowStatus::Refunded && (min..=max).contains(&c.amount))
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_LIMIT) as usize)
+            .collect()
+    }))
+}
+
+#[query]
+fn get_campaign_contributions(campaign_id: u64) -> Vec<Contribution> {
+    let mut res: Vec<Contribution> = Vec::new();
+    CONTRIBUTIONS.with(|m| {
+        for (_, v) in m.borrow().iter() {
+            if v.campaign_id == campaign_id {
+                res.push(v.clone());
+            }
+        }
+    });
+    res
+}
+
+/// One page of a campaign's contributions in id order; `limit` is clamped to `MAX_PAGE_LIMIT`.
+#[query]
+fn get_campaign_contributions_paged(campaign_id: u64, offset: u64, limit: u64) -> Vec<Contribution> {
+    CONTRIBUTIONS.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, c)| c)
+            .filter(|c| c.campaign_id == campaign_id)
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_LIMIT) as usize)
+            .collect()
+    })
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct EscrowSummary {
+    pub campaign_id: u64,
+    pub total_pending: u64,
+    pub total_held: u64,
+    pub total_released: u64,
+    pub total_refunded: u64,
+    pub tax_held: u64, // VAT included in `total_held`
+    pub net_held: u64, // `total_held` minus `tax_held`
+}
+
+impl EscrowSummary {
+    fn empty(campaign_id: u64) -> Self {
+        EscrowSummary {
+            campaign_id,
+            total_pending: 0,
+            total_held: 0,
+            total_released: 0,
+            total_refunded: 0,
+            tax_held: 0,
+            net_held: 0,
+        }
+    }
+
+    fn add(&mut self, c: &Contribution) {
+        match c.status {
+            EscrowStatus::Pending => self.total_pending = self.total_pending.saturating_add(c.amount),
+            EscrowStatus::Held => {
+                let tax = c.tax_amount.unwrap_or(0);
+                self.total_held = self.total_held.saturating_add(c.amount);
+                self.tax_held = self.tax_held.saturating_add(tax);
+                self.net_held = self.net_held.saturating_add(c.amount.saturating_sub(tax));
+            }
+            EscrowStatus::Released => self.total_released = self.total_released.saturating_add(c.amount),
+            EscrowStatus::Refunded => self.total_refunded = self.total_refunded.saturating_add(c.amount),
+            EscrowStatus::Expired | EscrowStatus::Cancelled => {}
+        }
+    }
+}
+
+#[query]
+fn get_escrow_summary(campaign_id: u64) -> EscrowSummary {
+    let mut s = EscrowSummary::empty(campaign_id);
+    CONTRIBUTIONS.with(|m| {
+        for (_, c) in m.borrow().iter() {
+            if c.campaign_id != campaign_id { continue; }
+            s.add(&c);
+        }
+    });
+    s
+}
+
+/// Escrow summaries for several campaigns in one pass over the contributions, in input order.
+/// Only the first `MAX_BATCH_SIZE` ids are served.
+#[query]
+fn get_escrow_summary_batch(mut campaign_ids: Vec<u64>) -> Vec<EscrowSummary> {
+    campaign_ids.truncate(MAX_BATCH_SIZE);
+    let mut acc: BTreeMap<u64, EscrowSummary> =
+        campaign_ids.iter().map(|id| (*id, EscrowSummary::empty(*id))).collect();
+    CONTRIBUTIONS.with(|m| {
+        for (_, c) in m.borrow().iter() {
+            if let Some(s) = acc.get_mut(&c.campaign_id) {
+                s.add(&c);
+            }
+        }
+    });
+    campaign_ids.iter().map(|id| acc[id].clone()).collect()
+}
+
+/// Point-in-time funding state of a campaign for accounting exports. `escrow` always covers the
+/// whole campaign; `contributions` and `transfers` are one page each (see `export_campaign_snapshot`).
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CampaignSnapshot {
+    pub campaign_id: u64,
+    pub taken_at_ns: u64,
+    pub escrow: EscrowSummary,
+    pub contributions: Vec<Contribution>, // by contribution id
+    pub transfers: Vec<ICPTransfer>,      // ledger transfers with the campaign id as memo, by transfer id
+    pub next_offset: Option<u64>,         // set while either list has more entries
+}
+
+/// Snapshot a campaign's escrow totals, contributions and ICP transfers (owner only).
+/// Both lists are paged with the same `offset`/`limit` so a reply stays under the message limit;
+/// keep calling with `next_offset` until it is `None`.
+#[query]
+fn export_campaign_snapshot(campaign_id: u64, offset: u64, limit: u64) -> Result<CampaignSnapshot, String> {
+    ensure_owner()?;
+    let limit = limit.min(MAX_PAGE_LIMIT) as usize;
+    let mut escrow = EscrowSummary::empty(campaign_id);
+    let mut contributions = Vec::new();
+    let mut more = false;
+    CONTRIBUTIONS.with(|m| {
+        for (i, (_, c)) in m.borrow().iter().filter(|(_, c)| c.campaign_id == campaign_id).enumerate() {
+            escrow.add(&c);
+            if i >= offset as usize {
+                if contributions.len() < limit { contributions.push(c) } else { more = true }
+            }
+        }
+    });
+    let transfers: Vec<ICPTransfer> = ICP_TRANSFERS.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, t)| t)
+            .filter(|t| t.memo == campaign_id)
+            .skip(offset as usize)
+            .take(limit + 1)
+            .collect()
+    });
+    more |= transfers.len() > limit;
+    let transfers = transfers.into_iter().take(limit).collect();
+    Ok(CampaignSnapshot {
+        campaign_id,
+        taken_at_ns: now_ns(),
+        escrow,
+        contributions,
+        transfers,
+        next_offset: more.then_some(offset.saturating_add(limit as u64)),
+    })
+}
+
+/// Progress bands (25/50/75/100) already announced for a campaign, ascending.
+#[query]
+fn get_progress_thresholds_crossed(campaign_id: u64) -> Vec<u8> {
+    let highest = PROGRESS_CROSSED.with(|m| m.borrow().get(&campaign_id).unwrap_or(0));
+    (1..=highest / PROGRESS_BAND_PERCENT).map(|i| i * PROGRESS_BAND_PERCENT).collect()
+}
+
+// ---------- Event Queries ----------
+
+/// Choose which events about the caller's own contributions are emitted.
+#[update]
+fn set_notification_prefs(prefs: NotificationPrefs) -> Result<(), String> {
+    ensure_not_paused()?;
+    let caller = caller();
+    if caller == Principal::anonymous() {
+        return Err("anonymous callers can't set preferences".into());
+    }
+    NOTIFICATION_PREFS.with(|m| m.borrow_mut().insert(Pk::from(caller), prefs));
+    Ok(())
+}
+
+/// The caller's notification preferences (all on unless changed).
+#[query]
+fn get_notification_prefs() -> NotificationPrefs {
+    NOTIFICATION_PREFS.with(|m| m.borrow().get(&Pk::from(caller()))).unwrap_or_default()
+}
+
+/// Events in emission order, for listeners polling the log.
+#[query]
+fn get_events(offset: u64, limit: u64) -> Vec<FundEvent> {
+    EVENTS.with(|m| {
+        m.borrow()
+            .iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_LIMIT) as usize)
+            .map(|(_, e)| e)
+            .collect()
+    })
+}
+
+/// Contributions flagged by the post-upgrade ICP transfer link check, with the reason.
+#[query]
+fn get_icp_link_issues() -> Vec<(u64, String)> {
+    ICP_LINK_ISSUES.with(|m| m.borrow().iter().collect())
+}
+
+#[query]
+fn get_global_stats() -> GlobalStats {
+    let mut contributions: u64 = 0;
+    let mut total_tips_e8s: u64 = 0;
+    let mut campaigns: BTreeSet<u64> = BTreeSet::new();
+    CONTRIBUTIONS.with(|m| {
+        for (_, c) in m.borrow().iter() {
+            contributions += 1;
+            campaigns.insert(c.campaign_id);
+            if c.confirmed_at_ns.is_some() {
+                total_tips_e8s = total_tips_e8s.saturating_add(c.tip_e8s.unwrap_or(0));
+            }
+        }
+    });
+    GlobalStats {
+        contributions,
+        users: USERS.with(|u| u.borrow().len()),
+        campaigns_backed: campaigns.len() as u64,
+        total_tips_e8s,
+    }
+}
+
+/// Payment-method mix across the platform: (method, total amount, count) of non-refunded
+/// contributions, one entry per method (`Other` grouped by its inner value), in first-seen order.
+#[query]
+fn get_platform_method_stats() -> Vec<(PaymentMethod, u64, u64)> {
+    let mut stats: Vec<(PaymentMethod, u64, u64)> = Vec::new();
+    CONTRIBUTIONS.with(|m| {
+        for (_, c) in m.borrow().iter() {
+            if c.status == EscrowStatus::Refunded { continue; }
+            match stats.iter_mut().find(|(method, _, _)| *method == c.method) {
+                Some(entry) => {
+                    entry.1 = entry.1.saturating_add(c.amount);
+                    entry.2 += 1;
+                }
+                None => stats.push((c.method.clone(), c.amount, 1)),
+            }
+        }
+    });
+    stats
+}
+
+// ---------- Standards ----------
+
+/// ICRC-10 introspection for wallets and explorers. ICP contributions move through the ledger's
+/// ICRC-1 transfers and ICRC-2 approve/transfer_from; this canister is not itself a token ledger.
+#[query]
+fn icrc10_supported_standards() -> Vec<SupportedStandard> {
+    vec![
+        SupportedStandard {
+            name: "ICRC-10".into(),
+            url: "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-10/ICRC-10.md".into(),
+        },
+        SupportedStandard {
+            name: "ICRC-1".into(),
+            url: "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-1".into(),
+        },
+        SupportedStandard {
+            name: "ICRC-2".into(),
+            url: "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-2".into(),
+        },
+    ]
+}
+
+// ---------- ICP Transfer Queries ----------
+
+/// Status, block height and fee of a single transfer.
+#[query]
+fn get_icp_transfer_by_id(transfer_id: u64) -> Option<ICPTransfer> {
+    get_icp_transfer(transfer_id)
+}
+
+#[query]
+fn get_icp_transfers_by_user(p: Option<Principal>) -> Vec<ICPTransfer> {
+    let who = p.unwrap_or(caller());
+    let mut res: Vec<ICPTransfer> = Vec::new();
+    ICP_TRANSFERS.with(|m| {
+        for (_, v) in m.borrow().iter() {
+            if v.from == who {
+                res.push(v.clone());
+            }
+        }
+    });
+    res
+}
+
+/// ICP transfers carrying `campaign_id` as memo, optionally filtered by status, oldest first.
+#[query]
+fn get_transfers_by_campaign(campaign_id: u64, status: Option<ICPTransferStatus>, offset: u64, limit: u64) -> Vec<ICPTransfer> {
+    let mut res: Vec<ICPTransfer> = ICP_TRANSFERS.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(_, t)| t)
+            .filter(|t| t.memo == campaign_id && status.as_ref().is_none_or(|s| t.status == *s))
+            .collect()
+    });
+    res.sort_by_key(|t| (t.created_at_ns, t.id));
+    res.into_iter()
+        .skip(offset as usize)
+        .take(limit.min(MAX_PAGE_LIMIT) as usize)
+        .collect()
+}
+
+// ---------- Init / Export ----------
+// ---------- Schema versioning ----------
+//
+// Stored records are candid, which still decodes older blobs as long as every field added since
+// is an `Option` (missing => None), so adding optional fields needs no migration. Anything that
+// breaks that (renames, type changes, new required fields) bumps `SCHEMA_VERSION` and adds a
+// step to `migrate_schema` that rewrites the affected maps from the previous version. The version
+// the data was written with lives in `STORED_SCHEMA_VERSION`; 0 means it predates versioning.
+
+/// Refuse data written by a newer schema; an older wasm would misread or drop what it added.
+fn check_schema_version(stored: u32) -> Result<(), String> {
+    if stored > SCHEMA_VERSION {
+        return Err(format!("stable data uses schema v{}, newer than v{}; refusing to downgrade", stored, SCHEMA_VERSION));
+    }
+    Ok(())
+}
+
+/// Bring stable data up to `SCHEMA_VERSION`, one step at a time. Refuses (trapping, which rolls
+/// the upgrade back) to run against data written by a newer schema.
+fn migrate_schema() {
+    let stored = STORED_SCHEMA_VERSION.with(|c| *c.borrow().get());
+    if let Err(e) = check_schema_version(stored) {
+        ic_cdk::trap(&e);
+    }
+    for from in stored..SCHEMA_VERSION {
+        match from {
+            // v0 -> v1: versioning introduced; every field added so far is optional
+            0 => {}
+            // v1 -> v2: contribution ids come from a counter; start it past every existing id
+            1 => {
+                let last = CONTRIBUTIONS.with(|m| m.borrow().last_key_value().map(|(k, _)| k)).unwrap_or(0);
+                LAST_CONTRIBUTION_ID.with(|c| c.borrow_mut().set(last).expect("seed contribution id"));
+            }
+            // v2 -> v3: same for ICP transfer ids
+            2 => {
+                let last = ICP_TRANSFERS.with(|m| m.borrow().last_key_value().map(|(k, _)| k)).unwrap_or(0);
+                LAST_TRANSFER_ID.with(|c| c.borrow_mut().set(last).expect("seed transfer id"));
+            }
+            _ => unreachable!("no migration from schema v{}", from),
+        }
+        ic_cdk::println!("migrated stable schema v{} -> v{}", from, from + 1);
+    }
+    STORED_SCHEMA_VERSION.with(|c| c.borrow_mut().set(SCHEMA_VERSION).expect("set schema version"));
+}
+
+#[init]
+fn init() {
+    let installer = caller();
+    OWNER.with(|o| o.borrow_mut().set(installer).expect("set owner"));
+    STORED_SCHEMA_VERSION.with(|c| c.borrow_mut().set(SCHEMA_VERSION).expect("set schema version"));
+    ic_cdk::println!("Funding canister initialized — {}", CANISTER_VERSION);
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    migrate_schema();
+    seed_owner_on_upgrade();
+    let flagged = check_icp_transfer_links();
+    ic_cdk::println!("Funding canister upgraded — {} ({} ICP link issues)", CANISTER_VERSION, flagged);
+}
+
+ic_cdk::export_candid!();
+
+/// Stand-ins for the system API and the backend/ledger canisters in unit tests.
+#[cfg(test)]
+mod mock {
+    use super::*;
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+
+    pub const CANISTER_ID: Principal = Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 1, 1, 1]);
+
+    thread_local! {
+        pub static NOW_NS: Cell<u64> = const { Cell::new(0) };
+        pub static CALLER: Cell<Principal> = const { Cell::new(Principal::anonymous()) };
+        // what the backend's `get_campaign_meta` returns, by campaign id
+        pub static CAMPAIGN_META: RefCell<BTreeMap<u64, CampaignMeta>> = const { RefCell::new(BTreeMap::new()) };
+        // notifications the backend accepted, and the error it answers with instead (if any)
+        pub static NOTIFICATIONS: RefCell<Vec<BackendNotification>> = const { RefCell::new(Vec::new()) };
+        pub static NOTIFY_ERROR: RefCell<Option<FundError>> = const { RefCell::new(None) };
+        // run once while the next notification is in flight, standing in for an interleaved message
+        pub static ON_NOTIFY: RefCell<Option<Box<dyn FnOnce()>>> = const { RefCell::new(None) };
+        // scripted ledger replies, consumed in order; an empty queue accepts every transfer
+        pub static LEDGER_REPLIES: RefCell<VecDeque<Result<u64, String>>> = const { RefCell::new(VecDeque::new()) };
+        pub static LEDGER_CALLS: RefCell<Vec<(Principal, Principal, u64, u64)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    pub fn ledger_transfer(from: Principal, to: Principal, amount_e8s: u64, memo: u64) -> Result<u64, String> {
+        let block = LEDGER_CALLS.with(|c| {
+            let mut c = c.borrow_mut();
+            c.push((from, to, amount_e8s, memo));
+            c.len() as u64
+        });
+        LEDGER_REPLIES.with(|r| r.borrow_mut().pop_front()).unwrap_or(Ok(block))
+    }
+
+    /// The memo of a block `ledger_transfer` handed out, read back from the recorded calls.
+    pub fn block_memo(block_index: u64) -> Option<u64> {
+        let index = usize::try_from(block_index.checked_sub(1)?).ok()?;
+        LEDGER_CALLS.with(|c| c.borrow().get(index).map(|call| call.3))
+    }
+
+    pub fn send_notification(n: &BackendNotification) -> Result<(), FundError> {
+        if let Some(interleaved) = ON_NOTIFY.with(|h| h.borrow_mut().take()) {
+            interleaved();
+        }
+        if let Some(e) = NOTIFY_ERROR.with(|e| e.borrow().clone()) {
+            return Err(e);
+        }
+        NOTIFICATIONS.with(|v| v.borrow_mut().push(n.clone()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    /// Drive an endpoint whose awaits all resolve immediately against `mock`.
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = std::pin::pin!(f);
+        match f.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(out) => out,
+            Poll::Pending => panic!("future is waiting on a real inter-canister call"),
+        }
+    }
+
+    fn user(n: u8) -> Principal {
+        Principal::from_slice(&[n; 29])
+    }
+
+    fn as_caller(p: Principal) {
+        mock::CALLER.with(|c| c.set(p));
+    }
+
+    fn set_now_secs(secs: u64) {
+        mock::NOW_NS.with(|n| n.set(secs * 1_000_000_000));
+    }
+
+    fn backend() -> Principal {
+        Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 2, 1, 1])
+    }
+
+    /// Register a campaign with the mocked backend.
+    fn add_campaign(campaign_id: u64, goal: u64, end_date_secs: u64) -> CampaignMeta {
+        let meta = CampaignMeta {
+            campaign_id,
+            goal,
+            amount_raised: 0,
+            end_date_secs,
+            owner_wallet: Some(user(200)),
+            currency: None,
+            decimals: None,
+            tax_rate_bps: None,
+            combined_max_per_backer: None,
+            creator: Some(user(201)),
+            closed_at: None,
+        };
+        mock::CAMPAIGN_META.with(|m| m.borrow_mut().insert(campaign_id, meta.clone()));
+        meta
+    }
+
+    fn contribution(id: u64, campaign_id: u64, backer: Principal, amount: u64, method: PaymentMethod, status: EscrowStatus) -> Contribution {
+        Contribution {
+            id,
+            campaign_id,
+            backer,
+            amount,
+            method,
+            status,
+            created_at_ns: now_ns(),
+            confirmed_at_ns: None,
+            icp_transfer_id: None,
+            matched_for: None,
+            imported_at_ns: None,
+            refunded_at_ns: None,
+            tax_rate_bps: None,
+            tax_amount: None,
+            tip_e8s: None,
+            rolled_over_from: None,
+        }
+    }
+
+    #[test]
+    fn contribution_ids_are_not_reused_after_a_removal() {
+        let ids: Vec<u64> = (0..3).map(|_| next_contribution_id()).collect();
+        for id in &ids {
+            insert_contribution(contribution(*id, 1, user(1), 100, PaymentMethod::BankTransfer, EscrowStatus::Pending));
+        }
+        CONTRIBUTIONS.with(|m| m.borrow_mut().remove(&2));
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(next_contribution_id(), 4);
+    }
+
+    #[test]
+    fn schema_migration_seeds_the_id_counters_past_existing_records() {
+        for id in [3, 7] {
+            insert_contribution(contribution(id, 1, user(1), 100, PaymentMethod::BankTransfer, EscrowStatus::Pending));
+        }
+        let transfer_id = record_icp_transfer(user(1), canister_id(), 100, 1);
+        LAST_TRANSFER_ID.with(|c| c.borrow_mut().set(0).unwrap());
+        LAST_CONTRIBUTION_ID.with(|c| c.borrow_mut().set(0).unwrap());
+        STORED_SCHEMA_VERSION.with(|c| c.borrow_mut().set(1).unwrap());
+        migrate_schema();
+        assert_eq!(next_contribution_id(), 8);
+        assert_eq!(next_transfer_id(), transfer_id + 1);
+    }
+
+    #[test]
+    fn transfer_ids_increase_even_when_one_is_removed() {
+        let first = block_on(initiate_icp_transfer(user(1), canister_id(), 100, 1)).unwrap();
+        ICP_TRANSFERS.with(|m| m.borrow_mut().remove(&first));
+        let second = block_on(initiate_icp_transfer(user(1), canister_id(), 100, 1)).unwrap();
+        assert!(second > first);
+        // contributions count separately
+        assert_eq!(next_contribution_id(), 1);
+    }
+
+    fn set_owner(p: Principal) {
+        OWNER.with(|o| o.borrow_mut().set(p).unwrap());
+    }
+
+    #[test]
+    fn recent_users_are_owner_only() {
+        set_owner(user(9));
+        as_caller(user(1));
+        register_user("Ann".into(), "ann@example.com".into()).unwrap();
+        assert!(get_recent_users(10).is_err());
+        as_caller(user(9));
+        assert_eq!(get_recent_users(10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn upgrade_seeds_a_missing_owner_but_keeps_an_existing_one() {
+        as_caller(user(7));
+        seed_owner_on_upgrade();
+        assert_eq!(owner(), user(7));
+        as_caller(user(8));
+        seed_owner_on_upgrade();
+        assert_eq!(owner(), user(7));
+    }
+
+    #[test]
+    fn only_allowlisted_gateways_confirm_fiat_payments() {
+        set_owner(user(9));
+        as_caller(user(9));
+        add_gateway(user(5)).unwrap();
+        insert_contribution(contribution(1, 1, user(1), 500, PaymentMethod::BankTransfer, EscrowStatus::Pending));
+
+        as_caller(user(6));
+        assert_eq!(block_on(confirm_payment(1, user(100))), Err("not authorized to confirm".to_string()));
+        assert_eq!(get_contribution(1).unwrap().status, EscrowStatus::Pending);
+
+        as_caller(user(5));
+        block_on(confirm_payment(1, user(100))).unwrap();
+        assert_eq!(get_contribution(1).unwrap().status, EscrowStatus::Held);
+    }
+
+    #[test]
+    fn import_reports_each_skipped_record_with_its_reason() {
+        set_owner(user(9));
+        as_caller(user(9));
+        add_campaign(1, 1_000, 10_000);
+        let record = |campaign_id: u64, amount: u64, method: PaymentMethod| ImportedContribution {
+            campaign_id,
+            backer: user(1),
+            amount,
+            method,
+            created_at_ns: 5,
+        };
+        let results = block_on(import_contributions(backend(), vec![
+            record(1, 100, PaymentMethod::BankTransfer),
+            record(1, 0, PaymentMethod::BankTransfer),
+            record(2, 100, PaymentMethod::BankTransfer),
+            record(1, 100, PaymentMethod::Other("icp".into())),
+        ]))
+        .unwrap();
+
+        assert_eq!(results.len(), 4);
+        let id = results[0].clone().unwrap();
+        assert_eq!(get_contribution(id).unwrap().imported_at_ns, Some(now_ns()));
+        assert_eq!(results[1], Err("amount must be > 0".to_string()));
+        assert_eq!(results[2], Err("campaign 2 not found".to_string()));
+        assert!(results[3].is_err());
+
+        as_caller(user(1));
+        assert!(block_on(import_contributions(backend(), vec![])).is_err());
+    }
+
+    #[test]
+    fn refund_cooldown_blocks_recontributing_only_while_enabled() {
+        set_now_secs(1_000);
+        let mut refunded = contribution(1, 7, user(1), 100, PaymentMethod::BankTransfer, EscrowStatus::Refunded);
+        refunded.refunded_at_ns = Some(now_ns());
+        insert_contribution(refunded);
+        set_now_secs(1_060);
+        assert!(check_refund_cooldown(user(1), 7).is_ok(), "disabled by default");
+
+        RE_CONTRIBUTE_COOLDOWN_SECS.with(|c| c.borrow_mut().set(120).unwrap());
+        assert!(check_refund_cooldown(user(1), 7).is_err());
+        assert!(check_refund_cooldown(user(2), 7).is_ok());
+        assert!(check_refund_cooldown(user(1), 8).is_ok());
+        set_now_secs(1_120);
+        assert!(check_refund_cooldown(user(1), 7).is_ok());
+    }
+
+    #[test]
+    fn a_transfer_whose_ledger_memo_names_another_campaign_is_flagged_not_credited() {
+        // the stored record says campaign 7, the block on the ledger says 8
+        let block = mock::ledger_transfer(user(1), canister_id(), 100, 8).unwrap();
+        let transfer_id = record_icp_transfer(user(1), canister_id(), 100, 7);
+        let mut t = get_icp_transfer(transfer_id).unwrap();
+        t.status = ICPTransferStatus::Confirmed;
+        t.block_height = Some(block);
+        update_icp_transfer(transfer_id, t);
+        let mut c = contribution(1, 7, user(1), 100, PaymentMethod::ICP, EscrowStatus::Pending);
+        c.icp_transfer_id = Some(transfer_id);
+        insert_contribution(c);
+
+        as_caller(user(1));
+        let err = block_on(confirm_payment(1, backend())).unwrap_err();
+        assert_eq!(err, "ICP transfer memo does not match campaign");
+        assert_eq!(get_icp_transfer(transfer_id).unwrap().status, ICPTransferStatus::Confirmed, "not downgraded");
+        assert_eq!(get_contribution(1).unwrap().status, EscrowStatus::Pending);
+        assert!(mock::NOTIFICATIONS.with(|n| n.borrow().is_empty()));
+        let flagged = EVENTS.with(|m| m.borrow().iter().map(|(_, e)| e.kind).last());
+        assert!(matches!(
+            flagged,
+            Some(FundEventKind::TransferMemoMismatch { transfer_id: t, campaign_id: 7, memo: 8 }) if t == transfer_id
+        ));
+    }
+
+    #[test]
+    fn only_unpaid_pending_contributions_expire_and_only_past_the_ttl() {
+        set_owner(user(9));
+        as_caller(user(9));
+        set_pending_ttl_secs(100).unwrap();
+        let paid = record_icp_transfer(user(3), canister_id(), 50, 7);
+        let mut t = get_icp_transfer(paid).unwrap();
+        t.status = ICPTransferStatus::Confirmed;
+        update_icp_transfer(paid, t);
+        let unpaid = record_icp_transfer(user(4), canister_id(), 50, 7);
+
+        set_now_secs(1_000);
+        insert_contribution(contribution(1, 7, user(1), 10, PaymentMethod::BankTransfer, EscrowStatus::Pending));
+        let mut c = contribution(3, 7, user(3), 50, PaymentMethod::ICP, EscrowStatus::Pending);
+        c.icp_transfer_id = Some(paid);
+        insert_contribution(c);
+        let mut c = contribution(4, 7, user(4), 50, PaymentMethod::ICP, EscrowStatus::Pending);
+        c.icp_transfer_id = Some(unpaid);
+        insert_contribution(c);
+        set_now_secs(1_002);
+        insert_contribution(contribution(2, 7, user(2), 10, PaymentMethod::BankTransfer, EscrowStatus::Pending));
+
+        set_now_secs(1_101);
+        assert_eq!(expire_stale_pending(), Ok(2));
+        let status = |id| get_contribution(id).unwrap().status;
+        assert_eq!(status(1), EscrowStatus::Expired, "just over the TTL");
+        assert_eq!(status(2), EscrowStatus::Pending, "just under the TTL");
+        assert_eq!(status(3), EscrowStatus::Pending, "ICP already in escrow");
+        assert_eq!(status(4), EscrowStatus::Expired);
+    }
+
+    #[test]
+    fn contributions_are_blocked_while_paused_and_the_flag_survives_upgrades() {
+        set_owner(user(9));
+        add_campaign(1, 1_000, 10_000);
+        as_caller(user(1));
+        register_user("Ann".into(), "ann@example.com".into()).unwrap();
+
+        as_caller(user(9));
+        set_paused(true).unwrap();
+        post_upgrade();
+        assert!(is_paused());
+
+        as_caller(user(1));
+        let paused = Err("canister paused".to_string());
+        assert_eq!(block_on(contribute(backend(), 1, 10, PaymentMethod::BankTransfer)), paused);
+        assert_eq!(block_on(contribute_icp(backend(), 1, 10, None, None)), paused);
+        assert!(set_paused(false).is_err());
+
+        as_caller(user(9));
+        set_paused(false).unwrap();
+        as_caller(user(1));
+        assert!(block_on(contribute(backend(), 1, 10, PaymentMethod::BankTransfer)).is_ok());
+    }
+
+    #[test]
+    fn contribution_amounts_convert_to_the_goal_unit() {
+        let mut meta = add_campaign(1, 0, 0);
+        assert_eq!(to_campaign_units(&PaymentMethod::Fawry, 1_234, &meta), Ok(1_234), "no currency: raw amounts");
+
+        meta.currency = Some("EGP".into());
+        meta.decimals = Some(0);
+        assert_eq!(to_campaign_units(&PaymentMethod::Fawry, 1_299, &meta), Ok(12));
+        meta.decimals = Some(4);
+        assert_eq!(to_campaign_units(&PaymentMethod::PayMob, 1_299, &meta), Ok(129_900));
+        assert!(to_campaign_units(&PaymentMethod::ICP, 1, &meta).is_err());
+        assert!(to_campaign_units(&PaymentMethod::PayMob, u64::MAX, &meta).is_err());
+
+        meta.currency = Some("ICP".into());
+        meta.decimals = Some(8);
+        assert_eq!(to_campaign_units(&PaymentMethod::ICP, 5, &meta), Ok(5));
+        assert!(to_campaign_units(&PaymentMethod::BankTransfer, 5, &meta).is_err());
+    }
+
+    #[test]
+    fn release_checks_the_goal_in_campaign_units_not_raw_amounts() {
+        let mut meta = add_campaign(1, 100, 1_000);
+        meta.currency = Some("EGP".into());
+        meta.decimals = Some(0);
+        mock::CAMPAIGN_META.with(|m| m.borrow_mut().insert(1, meta));
+        // 50.00 EGP in piastres: 5_000 raw, but only 50 of the 100 EGP goal
+        insert_contribution(contribution(1, 1, user(1), 5_000, PaymentMethod::Fawry, EscrowStatus::Held));
+        set_now_secs(1_001);
+
+        assert_eq!(block_on(release_campaign(backend(), 1)), Err("campaign did not reach goal".to_string()));
+        insert_contribution(contribution(2, 1, user(2), 5_000, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        assert_eq!(block_on(release_campaign(backend(), 1)), Ok(2));
+    }
+
+    #[test]
+    fn below_goal_campaigns_release_only_through_the_owner_override() {
+        set_owner(user(9));
+        add_campaign(1, 1_000, 1_000);
+        insert_contribution(contribution(1, 1, user(1), 400, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        set_now_secs(1_001);
+
+        as_caller(user(1));
+        assert_eq!(block_on(release_campaign(backend(), 1)), Err("campaign did not reach goal".to_string()));
+        assert!(block_on(force_release_campaign(backend(), 1, "keep what was raised".into())).is_err());
+
+        as_caller(user(9));
+        assert!(block_on(force_release_campaign(backend(), 1, " ".into())).is_err(), "a reason is required");
+        assert_eq!(block_on(force_release_campaign(backend(), 1, "keep what was raised".into())), Ok(1));
+        assert_eq!(get_contribution(1).unwrap().status, EscrowStatus::Released);
+        let events = get_events(0, 10);
+        assert!(events.iter().any(|e| matches!(
+            &e.kind,
+            FundEventKind::ForceReleased { campaign_id: 1, total_amount: 400, by, .. } if *by == user(9)
+        )));
+    }
+
+    #[test]
+    fn a_released_campaign_cannot_then_be_refunded() {
+        add_campaign(1, 100, 1_000);
+        insert_contribution(contribution(1, 1, user(1), 100, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        set_now_secs(1_001);
+        assert_eq!(block_on(release_campaign(backend(), 1)), Ok(1));
+
+        // even a late Held row and a goal that moved out of reach can't reopen it
+        insert_contribution(contribution(2, 1, user(2), 50, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        add_campaign(1, 1_000, 1_000);
+        assert_eq!(block_on(refund_campaign(backend(), 1)), Err("campaign already settled".to_string()));
+        assert_eq!(block_on(release_campaign(backend(), 1)), Err("campaign already settled".to_string()));
+        assert_eq!(get_contribution(2).unwrap().status, EscrowStatus::Held);
+    }
+
+    #[test]
+    fn vat_is_extracted_from_the_gross_amount_without_overflow() {
+        let mut meta = add_campaign(1, 1_000, 10_000);
+        assert_eq!(vat_breakdown(11_400, &meta), (None, None));
+        meta.tax_rate_bps = Some(0);
+        assert_eq!(vat_breakdown(11_400, &meta), (None, None));
+
+        meta.tax_rate_bps = Some(1_400); // 14%
+        assert_eq!(vat_breakdown(11_400, &meta), (Some(1_400), Some(1_400)));
+        assert_eq!(vat_breakdown(100, &meta), (Some(1_400), Some(12)), "rounds down");
+        let (_, tax) = vat_breakdown(u64::MAX, &meta);
+        assert_eq!(tax, Some(((u64::MAX as u128) * 1_400 / 11_400) as u64));
+    }
+
+    #[test]
+    fn contributions_record_vat_and_the_escrow_summary_splits_it_out() {
+        let mut meta = add_campaign(1, 100_000, 10_000);
+        meta.tax_rate_bps = Some(1_400);
+        mock::CAMPAIGN_META.with(|m| m.borrow_mut().insert(1, meta));
+        as_caller(user(1));
+        register_user("Ann".into(), "ann@example.com".into()).unwrap();
+        let id = block_on(contribute(backend(), 1, 11_400, PaymentMethod::BankTransfer)).unwrap();
+        let c = get_contribution(id).unwrap();
+        assert_eq!((c.amount, c.tax_rate_bps, c.tax_amount), (11_400, Some(1_400), Some(1_400)));
+
+        let mut held = c;
+        held.status = EscrowStatus::Held;
+        update_contribution(id, held);
+        let summary = get_escrow_summary(1);
+        assert_eq!((summary.total_held, summary.tax_held, summary.net_held), (11_400, 1_400, 10_000));
+    }
+
+    #[test]
+    fn a_tip_is_recorded_only_when_its_transfer_succeeds() {
+        add_campaign(1, 1_000_000, 10_000);
+        as_caller(user(1));
+        register_user("Ann".into(), "ann@example.com".into()).unwrap();
+        assert!(block_on(contribute_icp(backend(), 1, 1_000, Some(1_001), None)).is_err());
+
+        let tipped = block_on(contribute_icp(backend(), 1, 1_000, Some(50), None)).unwrap();
+        assert_eq!(get_contribution(tipped).unwrap().tip_e8s, Some(50));
+        let (_, to, amount, _) = mock::LEDGER_CALLS.with(|c| c.borrow()[1]);
+        assert_eq!((to, amount), (fee_collector(), 50));
+
+        mock::LEDGER_REPLIES.with(|r| r.borrow_mut().extend([Ok(10), Err("insufficient funds".to_string())]));
+        let untipped = block_on(contribute_icp(backend(), 1, 1_000, Some(50), None)).unwrap();
+        let c = get_contribution(untipped).unwrap();
+        assert_eq!(c.tip_e8s, None);
+        assert_eq!(get_icp_transfer(c.icp_transfer_id.unwrap()).unwrap().status, ICPTransferStatus::Confirmed);
+    }
+
+    fn confirmed_icp_contribution(id: u64, campaign_id: u64, backer: Principal, amount: u64) {
+        let transfer_id = record_icp_transfer(backer, canister_id(), amount, campaign_id);
+        let mut t = get_icp_transfer(transfer_id).unwrap();
+        t.status = ICPTransferStatus::Confirmed;
+        update_icp_transfer(transfer_id, t);
+        let mut c = contribution(id, campaign_id, backer, amount, PaymentMethod::ICP, EscrowStatus::Pending);
+        c.icp_transfer_id = Some(transfer_id);
+        insert_contribution(c);
+    }
+
+    /// `confirmed_icp_contribution`, already moved to Held.
+    fn held_icp_contribution(id: u64, campaign_id: u64, backer: Principal, amount: u64) {
+        confirmed_icp_contribution(id, campaign_id, backer, amount);
+        let mut c = get_contribution(id).unwrap();
+        c.status = EscrowStatus::Held;
+        c.confirmed_at_ns = Some(now_ns());
+        update_contribution(id, c);
+    }
+
+    #[test]
+    fn a_confirmation_interleaved_with_the_backend_call_is_rejected() {
+        confirmed_icp_contribution(1, 7, user(1), 100);
+        as_caller(user(1));
+        let second = std::rc::Rc::new(RefCell::new(None));
+        let seen = second.clone();
+        mock::ON_NOTIFY.with(|h| {
+            *h.borrow_mut() = Some(Box::new(move || *seen.borrow_mut() = Some(block_on(confirm_payment(1, backend())))))
+        });
+
+        assert_eq!(block_on(confirm_payment(1, backend())), Ok(()));
+        assert_eq!(*second.borrow(), Some(Err("contribution not pending".to_string())));
+        assert_eq!(mock::NOTIFICATIONS.with(|n| n.borrow().len()), 1, "credited once");
+        assert_eq!(get_contribution(1).unwrap().status, EscrowStatus::Held);
+    }
+
+    #[test]
+    fn a_rejected_backend_notification_puts_the_contribution_back_to_pending() {
+        confirmed_icp_contribution(1, 7, user(1), 100);
+        as_caller(user(1));
+        mock::NOTIFY_ERROR.with(|e| *e.borrow_mut() = Some(FundError::BackendRejected("campaign closed".into())));
+        assert!(block_on(confirm_payment(1, backend())).is_err());
+        let c = get_contribution(1).unwrap();
+        assert_eq!((c.status, c.confirmed_at_ns), (EscrowStatus::Pending, None));
+
+        mock::NOTIFY_ERROR.with(|e| *e.borrow_mut() = None);
+        assert_eq!(block_on(confirm_payment(1, backend())), Ok(()));
+        let BackendNotification::IcpContribution { is_new_backer, .. } = mock::NOTIFICATIONS.with(|n| n.borrow()[0].clone()) else {
+            panic!("expected an ICP contribution notification");
+        };
+        assert!(is_new_backer);
+    }
+
+    #[test]
+    fn a_refund_interleaved_with_a_release_is_rejected_while_it_settles() {
+        add_campaign(1, 100, 1_000);
+        insert_contribution(contribution(1, 1, user(1), 100, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        set_now_secs(1_001);
+        let refund = std::rc::Rc::new(RefCell::new(None));
+        let seen = refund.clone();
+        mock::ON_NOTIFY.with(|h| {
+            *h.borrow_mut() = Some(Box::new(move || *seen.borrow_mut() = Some(block_on(refund_campaign(backend(), 1)))))
+        });
+
+        assert_eq!(block_on(release_campaign(backend(), 1)), Ok(1));
+        assert_eq!(*refund.borrow(), Some(Err("campaign settlement in progress".to_string())));
+        assert!(SETTLING.with(|s| s.borrow().is_empty()), "guard released");
+    }
+
+    #[test]
+    fn outbound_transfers_pay_the_ledger_fee_out_of_the_amount() {
+        assert_eq!(net_of_ledger_fee(ICP_LEDGER_FEE_E8S), None, "dust stays in escrow");
+        assert_eq!(net_of_ledger_fee(ICP_LEDGER_FEE_E8S + 1), Some(1));
+
+        assert_eq!(block_on(send_from_escrow(user(1), ICP_LEDGER_FEE_E8S - 1, 7)), Ok(None));
+        let id = block_on(send_from_escrow(user(1), 1_000_000, 7)).unwrap().unwrap();
+        let t = get_icp_transfer(id).unwrap();
+        assert_eq!((t.from, t.to, t.amount_e8s), (canister_id(), user(1), 1_000_000 - ICP_LEDGER_FEE_E8S));
+        assert_eq!(t.fee_e8s, Some(ICP_LEDGER_FEE_E8S));
+        assert_eq!(mock::LEDGER_CALLS.with(|c| c.borrow().len()), 1);
+
+        // one payout transfer, so the fee is charged once on the ICP share
+        add_campaign(1, 100, 1_000);
+        held_icp_contribution(1, 1, user(1), 60_000);
+        held_icp_contribution(2, 1, user(2), 40_000);
+        let preview = block_on(preview_release(backend(), 1)).unwrap();
+        assert_eq!((preview.fee_e8s, preview.net_to_owner), (ICP_LEDGER_FEE_E8S, 100_000 - ICP_LEDGER_FEE_E8S));
+    }
+
+    #[test]
+    fn the_per_backer_cap_counts_icp_and_fiat_together() {
+        let mut meta = add_campaign(1, 1_000_000, 10_000);
+        meta.combined_max_per_backer = Some(1_000);
+        mock::CAMPAIGN_META.with(|m| m.borrow_mut().insert(1, meta));
+        as_caller(user(1));
+        register_user("Ann".into(), "ann@example.com".into()).unwrap();
+
+        block_on(contribute_icp(backend(), 1, 600, None, None)).unwrap();
+        let err = block_on(contribute(backend(), 1, 401, PaymentMethod::Fawry)).unwrap_err();
+        assert!(err.contains("per-backer cap of 1000 (600 already contributed)"), "{}", err);
+        block_on(contribute(backend(), 1, 400, PaymentMethod::Fawry)).unwrap();
+        assert!(block_on(contribute_icp(backend(), 1, 1, None, None)).is_err());
+
+        as_caller(user(2));
+        register_user("Bob".into(), "bob@example.com".into()).unwrap();
+        assert!(block_on(contribute(backend(), 1, 1_000, PaymentMethod::BankTransfer)).is_ok(), "caps are per backer");
+    }
+
+    #[test]
+    fn contribution_detail_joins_the_transfer_for_the_backer_and_owner_only() {
+        set_owner(user(9));
+        confirmed_icp_contribution(1, 7, user(1), 100);
+        let transfer_id = get_contribution(1).unwrap().icp_transfer_id;
+        assert!(get_contribution_detail(2).is_none());
+
+        for viewer in [user(1), user(9)] {
+            as_caller(viewer);
+            let d = get_contribution_detail(1).unwrap();
+            assert_eq!(d.contribution.backer, user(1));
+            assert_eq!(d.transfer.map(|t| t.id), transfer_id);
+        }
+
+        as_caller(user(2));
+        let d = get_contribution_detail(1).unwrap();
+        assert_eq!((d.contribution.backer, d.contribution.icp_transfer_id), (Principal::anonymous(), None));
+        assert!(d.transfer.is_none());
+    }
+
+    #[test]
+    fn an_other_method_spelled_icp_is_never_treated_as_icp() {
+        let other = |name: &str| normalize_method(PaymentMethod::Other(name.into()));
+        for look_alike in ["icp", " ICP ", "I.C.P", "Bank Transfer", "paymob"] {
+            assert!(other(look_alike).is_err(), "{:?} must be refused", look_alike);
+        }
+        assert!(other("   ").is_err());
+        assert_eq!(other(" Vodafone Cash "), Ok(PaymentMethod::Other("vodafone cash".into())));
+
+        add_campaign(1, 1_000, 10_000);
+        as_caller(user(1));
+        register_user("Ann".into(), "ann@example.com".into()).unwrap();
+        assert!(block_on(contribute(backend(), 1, 10, PaymentMethod::Other("Icp".into()))).is_err());
+        let id = block_on(contribute(backend(), 1, 10, PaymentMethod::Other("Vodafone Cash".into()))).unwrap();
+        // only ICP contributions may be confirmed by their backer
+        assert!(block_on(confirm_payment(id, backend())).is_err());
+        assert_eq!(get_contribution(id).unwrap().status, EscrowStatus::Pending);
+        assert!(mock::LEDGER_CALLS.with(|c| c.borrow().is_empty()));
+    }
+
+    #[test]
+    fn a_refund_tells_the_backend_how_much_went_back() {
+        add_campaign(1, 1_000, 1_000);
+        insert_contribution(contribution(1, 1, user(1), 100, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        insert_contribution(contribution(2, 1, user(2), 50, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        set_now_secs(1_001);
+        assert_eq!(block_on(refund_campaign(backend(), 1)), Ok(2));
+        let sent = mock::NOTIFICATIONS.with(|v| v.borrow().clone());
+        assert!(matches!(
+            sent.last(),
+            Some(BackendNotification::Refund { campaign_id: 1, total_amount: 150 })
+        ));
+    }
+
+    #[test]
+    fn cancelling_a_campaign_touches_only_unpaid_pending_rows() {
+        set_owner(user(9));
+        insert_contribution(contribution(1, 7, user(1), 10, PaymentMethod::BankTransfer, EscrowStatus::Pending));
+        insert_contribution(contribution(2, 7, user(2), 10, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        confirmed_icp_contribution(3, 7, user(3), 50);
+        insert_contribution(contribution(4, 8, user(4), 10, PaymentMethod::BankTransfer, EscrowStatus::Pending));
+
+        as_caller(user(1));
+        assert!(cancel_campaign_pending(7).is_err(), "owner only");
+        as_caller(user(9));
+        assert_eq!(cancel_campaign_pending(7), Ok(1));
+        let status = |id| get_contribution(id).unwrap().status;
+        assert_eq!(status(1), EscrowStatus::Cancelled);
+        assert_eq!(status(2), EscrowStatus::Held);
+        assert_eq!(status(3), EscrowStatus::Pending, "ICP already in escrow");
+        assert_eq!(status(4), EscrowStatus::Pending, "another campaign");
+        assert!(matches!(
+            get_events(0, 10).last().map(|e| &e.kind),
+            Some(FundEventKind::PendingCancelled { campaign_id: 7, count: 1, by }) if *by == user(9)
+        ));
+    }
+
+    #[test]
+    fn the_settlement_cutoff_adds_the_grace_unless_closed_early() {
+        let mut meta = add_campaign(1, 100, 1_000);
+        assert_eq!(settlement_cutoff(&meta), 1_000);
+        SETTLEMENT_GRACE_SECS.with(|c| c.borrow_mut().set(300).unwrap());
+        assert_eq!(settlement_cutoff(&meta), 1_300);
+        meta.end_date_secs = u64::MAX;
+        assert_eq!(settlement_cutoff(&meta), u64::MAX, "saturates");
+        meta.closed_at = Some(700);
+        assert_eq!(settlement_cutoff(&meta), 700);
+    }
+
+    #[test]
+    fn a_contribution_within_the_grace_window_is_accepted_unless_closed_early() {
+        set_owner(user(9));
+        as_caller(user(9));
+        set_settlement_grace_secs(300).unwrap();
+        let mut meta = add_campaign(1, 1_000, 1_000);
+        as_caller(user(1));
+        register_user("Ann".into(), "ann@example.com".into()).unwrap();
+
+        set_now_secs(1_200);
+        assert!(block_on(contribute(backend(), 1, 10, PaymentMethod::BankTransfer)).is_ok());
+        set_now_secs(1_300);
+        assert_eq!(
+            block_on(contribute(backend(), 1, 10, PaymentMethod::BankTransfer)),
+            Err("campaign already ended".to_string())
+        );
+
+        meta.end_date_secs = 1_100;
+        meta.closed_at = Some(1_100);
+        mock::CAMPAIGN_META.with(|m| m.borrow_mut().insert(1, meta));
+        set_now_secs(1_200);
+        assert_eq!(
+            block_on(contribute(backend(), 1, 10, PaymentMethod::BankTransfer)),
+            Err("campaign already ended".to_string()),
+            "closing early skips the grace"
+        );
+    }
+
+    #[test]
+    fn a_registration_past_the_stable_size_bound_is_refused() {
+        as_caller(user(1));
+        assert!(register_user("n".repeat(MAX_VALUE_SIZE as usize), "ann@example.com".into()).is_err());
+        assert_eq!(get_user_count(), 0, "nothing is stored");
+        register_user("Ann".into(), "ann@example.com".into()).unwrap();
+    }
+
+    #[test]
+    fn data_from_a_newer_schema_is_refused_on_upgrade() {
+        assert_eq!(check_schema_version(SCHEMA_VERSION), Ok(()));
+        assert_eq!(check_schema_version(0), Ok(()));
+        assert_eq!(
+            check_schema_version(SCHEMA_VERSION + 1),
+            Err(format!("stable data uses schema v{}, newer than v{}; refusing to downgrade", SCHEMA_VERSION + 1, SCHEMA_VERSION))
+        );
+        STORED_SCHEMA_VERSION.with(|c| c.borrow_mut().set(SCHEMA_VERSION + 1).unwrap());
+        assert!(std::panic::catch_unwind(migrate_schema).is_err());
+        assert_eq!(STORED_SCHEMA_VERSION.with(|c| *c.borrow().get()), SCHEMA_VERSION + 1, "left untouched");
+    }
+
+    #[test]
+    fn a_partial_fill_takes_only_the_room_left_up_to_exactly_the_goal() {
+        add_campaign(1, 1_000, 10_000);
+        add_campaign(2, 1_000, 10_000);
+        for campaign_id in [1, 2] {
+            insert_contribution(contribution(100 + campaign_id, campaign_id, user(2), 700, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        }
+        as_caller(user(1));
+        register_user("Ann".into(), "ann@example.com".into()).unwrap();
+
+        let exact = block_on(contribute_icp(backend(), 2, 300, None, Some(true))).unwrap();
+        assert_eq!(get_contribution(exact).unwrap().amount, 300, "exactly the room is taken whole");
+        let over = block_on(contribute_icp(backend(), 1, 500, None, Some(true))).unwrap();
+        assert_eq!(get_contribution(over).unwrap().amount, 300, "capped at the room");
+        let sent: Vec<u64> = mock::LEDGER_CALLS.with(|c| c.borrow().iter().map(|(_, _, amount, _)| *amount).collect());
+        assert_eq!(sent, vec![300, 300], "only the accepted amount is transferred");
+
+        let mut c = get_contribution(over).unwrap();
+        c.status = EscrowStatus::Held;
+        update_contribution(over, c);
+        assert_eq!(
+            block_on(contribute_icp(backend(), 1, 1, None, Some(true))),
+            Err("campaign already reached its goal".to_string())
+        );
+        assert!(block_on(contribute_icp(backend(), 1, 1, None, None)).is_ok(), "without partial fill it is taken as is");
+    }
+
+    #[test]
+    fn contributions_past_the_kyc_threshold_need_a_verified_backer() {
+        set_owner(user(9));
+        as_caller(user(9));
+        set_kyc_thresholds(1_000, 500).unwrap();
+        add_campaign(1, 1_000_000, 10_000);
+        as_caller(user(1));
+        register_user("Ann".into(), "ann@example.com".into()).unwrap();
+        let icp = |amount| block_on(contribute_icp(backend(), 1, amount, None, None));
+        let fiat = |amount| block_on(contribute(backend(), 1, amount, PaymentMethod::BankTransfer));
+        let refused = Err("verification required".to_string());
+
+        assert!(icp(999).is_ok(), "just below");
+        assert!(icp(1_000).is_ok(), "at the threshold");
+        assert_eq!(icp(1_001), refused, "just above");
+        assert!(fiat(500).is_ok());
+        assert_eq!(fiat(501), refused, "fiat has its own threshold");
+
+        assert!(set_user_verified(user(1), true).is_err(), "owner only");
+        as_caller(user(9));
+        assert!(set_user_verified(user(1), true).unwrap().verified_at_ns.is_some());
+        as_caller(user(1));
+        assert!(icp(1_001).is_ok());
+        assert!(fiat(501).is_ok());
+    }
+
+    #[test]
+    fn icp_below_the_refund_threshold_is_marked_refunded_but_not_sent() {
+        set_owner(user(9));
+        as_caller(user(9));
+        set_min_refundable_e8s(100_000).unwrap();
+        add_campaign(7, 10_000_000, 1_000);
+        confirmed_icp_contribution(1, 7, user(1), 99_999);
+        confirmed_icp_contribution(2, 7, user(2), 100_000);
+        let inbound = mock::LEDGER_CALLS.with(|c| c.borrow().len());
+
+        set_now_secs(1_001);
+        assert_eq!(block_on(refund_campaign(backend(), 7)), Ok(2));
+        assert_eq!(get_contribution(1).unwrap().status, EscrowStatus::Refunded);
+        assert_eq!(get_contribution(2).unwrap().status, EscrowStatus::Refunded);
+        let refunds: Vec<(Principal, u64)> =
+            mock::LEDGER_CALLS.with(|c| c.borrow()[inbound..].iter().map(|(_, to, amount, _)| (*to, *amount)).collect());
+        assert_eq!(refunds, vec![(user(2), 100_000 - ICP_LEDGER_FEE_E8S)], "only the one at the threshold goes back");
+    }
+
+    fn outbound_transfers() -> Vec<(Principal, u64)> {
+        mock::LEDGER_CALLS.with(|c| {
+            c.borrow().iter().filter(|(from, ..)| *from == canister_id()).map(|(_, to, amount, _)| (*to, *amount)).collect()
+        })
+    }
+
+    #[test]
+    fn a_release_the_ledger_refuses_leaves_the_funds_held_for_a_retry() {
+        let mut meta = add_campaign(1, 100, 1_000);
+        held_icp_contribution(1, 1, user(1), 100_000);
+        set_now_secs(1_001);
+
+        meta.owner_wallet = None;
+        mock::CAMPAIGN_META.with(|m| m.borrow_mut().insert(1, meta.clone()));
+        assert_eq!(block_on(release_campaign(backend(), 1)), Err("set a payout wallet before releasing ICP".to_string()));
+
+        meta.owner_wallet = Some(user(200));
+        mock::CAMPAIGN_META.with(|m| m.borrow_mut().insert(1, meta));
+        mock::LEDGER_REPLIES.with(|r| r.borrow_mut().push_back(Err("insufficient funds".to_string())));
+        assert!(block_on(release_campaign(backend(), 1)).unwrap_err().contains("insufficient funds"));
+        assert_eq!(get_contribution(1).unwrap().status, EscrowStatus::Held);
+        assert_eq!(get_campaign_settlement(1), None);
+        assert!(mock::NOTIFICATIONS.with(|v| v.borrow().is_empty()), "no payout reported");
+        let payouts = ICP_TRANSFERS.with(|m| {
+            m.borrow().iter().filter(|(_, t)| t.from == canister_id()).map(|(_, t)| t.status).collect::<Vec<_>>()
+        });
+        assert_eq!(payouts, vec![ICPTransferStatus::Failed]);
+
+        assert_eq!(block_on(release_campaign(backend(), 1)), Ok(1));
+        assert_eq!(get_contribution(1).unwrap().status, EscrowStatus::Released);
+        assert_eq!(outbound_transfers(), vec![(user(200), 100_000 - ICP_LEDGER_FEE_E8S); 2], "sent again, once");
+    }
+
+    #[test]
+    fn a_refund_the_ledger_refuses_stays_refundable_and_is_retried_alone() {
+        add_campaign(7, 10_000_000, 1_000);
+        confirmed_icp_contribution(1, 7, user(1), 100_000);
+        confirmed_icp_contribution(2, 7, user(2), 200_000);
+        insert_contribution(contribution(3, 7, user(3), 300, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        set_now_secs(1_001);
+
+        mock::LEDGER_REPLIES.with(|r| r.borrow_mut().push_back(Err("temporarily unavailable".to_string())));
+        assert_eq!(
+            block_on(refund_campaign(backend(), 7)),
+            Err("refunded 2 contributions; the ledger refused 1, call again to retry them".to_string())
+        );
+        let status = |id| get_contribution(id).unwrap().status;
+        assert_eq!((status(1), status(2), status(3)), (EscrowStatus::Pending, EscrowStatus::Refunded, EscrowStatus::Refunded));
+        assert_eq!(get_campaign_settlement(7), Some(EscrowStatus::Refunded));
+        assert!(block_on(release_campaign(backend(), 7)).is_err(), "no release after a partial refund");
+
+        assert_eq!(block_on(refund_campaign(backend(), 7)), Ok(1));
+        assert_eq!(status(1), EscrowStatus::Refunded);
+        let fee = ICP_LEDGER_FEE_E8S;
+        assert_eq!(
+            outbound_transfers(),
+            vec![(user(1), 100_000 - fee), (user(2), 200_000 - fee), (user(1), 100_000 - fee)],
+            "the refused refund is sent again and nobody is paid twice"
+        );
+        assert_eq!(REFUND_QUEUE.with(|q| q.borrow().len()), 1, "the fiat refund is queued once");
+    }
+
+    #[test]
+    fn the_transfer_record_follows_the_ledger_reply() {
+        mock::LEDGER_REPLIES.with(|r| r.borrow_mut().extend([Ok(42), Err("bad fee".to_string())]));
+        let ok = block_on(initiate_icp_transfer(user(1), canister_id(), 500, 7)).unwrap();
+        let t = get_icp_transfer(ok).unwrap();
+        assert_eq!((t.status, t.block_height), (ICPTransferStatus::Confirmed, Some(42)));
+        assert!(t.confirmed_at_ns.is_some());
+
+        let err = block_on(initiate_icp_transfer(user(1), canister_id(), 500, 7)).unwrap_err();
+        assert_eq!(err, format!("transfer {} failed: bad fee", ok + 1));
+        let t = get_icp_transfer(ok + 1).unwrap();
+        assert_eq!((t.status, t.block_height), (ICPTransferStatus::Failed, None));
+    }
+
+    #[test]
+    fn only_the_owner_or_a_gateway_confirm_fiat_and_ownership_moves_that_right() {
+        set_owner(user(9));
+        for id in 1..=3 {
+            insert_contribution(contribution(id, 1, user(1), 100, PaymentMethod::BankTransfer, EscrowStatus::Pending));
+        }
+        let refused = Err("not authorized to confirm".to_string());
+        for stranger in [user(1), user(5)] {
+            as_caller(stranger);
+            assert_eq!(block_on(confirm_payment(1, backend())), refused, "{}", stranger);
+        }
+
+        as_caller(user(9));
+        add_gateway(user(7)).unwrap();
+        assert_eq!(block_on(confirm_payment(1, backend())), Ok(()));
+        as_caller(user(7));
+        assert_eq!(block_on(confirm_payment(2, backend())), Ok(()));
+
+        as_caller(user(5));
+        assert!(transfer_ownership(user(5)).is_err(), "owner only");
+        as_caller(user(9));
+        assert!(transfer_ownership(Principal::anonymous()).is_err());
+        transfer_ownership(user(8)).unwrap();
+        assert_eq!(get_owner(), user(8));
+        let logged = EVENTS.with(|m| m.borrow().iter().map(|(_, e)| e.kind).last());
+        assert!(matches!(
+            logged,
+            Some(FundEventKind::OwnershipTransferred { from, to, by }) if (from, to, by) == (user(9), user(8), user(9))
+        ));
+        assert_eq!(block_on(confirm_payment(3, backend())), refused, "the old owner lost the right");
+        as_caller(user(8));
+        assert_eq!(block_on(confirm_payment(3, backend())), Ok(()));
+    }
+
+    #[test]
+    fn refunds_open_only_once_a_campaign_ended_below_its_goal() {
+        add_campaign(1, 100, 1_000);
+        add_campaign(2, 100, 1_000);
+        insert_contribution(contribution(1, 1, user(1), 60, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        insert_contribution(contribution(2, 2, user(1), 100, PaymentMethod::BankTransfer, EscrowStatus::Held));
+
+        set_now_secs(1_000);
+        assert_eq!(
+            block_on(refund_campaign(backend(), 1)),
+            Err("campaign still active; refunds open once it has ended".to_string())
+        );
+        set_now_secs(1_001);
+        assert_eq!(
+            block_on(refund_campaign(backend(), 2)),
+            Err("campaign reached its goal; release the funds instead".to_string())
+        );
+        assert_eq!(get_contribution(2).unwrap().status, EscrowStatus::Held);
+        assert_eq!(block_on(refund_campaign(backend(), 1)), Ok(1));
+        assert_eq!(get_contribution(1).unwrap().status, EscrowStatus::Refunded);
+        assert_eq!(
+            block_on(refund_campaign(backend(), 404)),
+            Err("campaign not found".to_string())
+        );
+    }
+
+    #[test]
+    fn contribution_pages_follow_id_order_without_overlap() {
+        for id in 1..=500 {
+            // every fifth row belongs to another campaign and must never show up
+            let campaign_id = if id % 5 == 0 { 2 } else { 1 };
+            insert_contribution(contribution(id, campaign_id, user(1), id, PaymentMethod::BankTransfer, EscrowStatus::Held));
+        }
+        let page = |offset, limit| -> Vec<u64> {
+            get_campaign_contributions_paged(1, offset, limit).into_iter().map(|c| c.id).collect()
+        };
+        let expected: Vec<u64> = (1..=500).filter(|id| id % 5 != 0).collect();
+
+        assert_eq!(page(0, 1_000).len(), MAX_PAGE_LIMIT as usize, "limit is clamped");
+        let mut seen = Vec::new();
+        for offset in (0..400).step_by(150) {
+            seen.extend(page(offset, 150));
+        }
+        assert_eq!(seen, expected);
+        assert_eq!(page(399, 10), vec![499]);
+        assert!(page(400, 10).is_empty());
+        assert!(page(0, 0).is_empty());
+    }
+
+    #[test]
+    fn a_transfer_can_be_read_back_by_id() {
+        let id = block_on(initiate_icp_transfer(user(1), canister_id(), 500, 7)).unwrap();
+        let t = get_icp_transfer_by_id(id).unwrap();
+        assert_eq!((t.id, t.from, t.amount_e8s, t.memo), (id, user(1), 500, 7));
+        assert_eq!((t.status, t.block_height), (ICPTransferStatus::Confirmed, Some(1)));
+        assert!(get_icp_transfer_by_id(id + 1).is_none());
+    }
+
+    #[test]
+    fn sponsor_matches_are_not_paid_out_and_unused_ones_go_back_to_the_pool() {
+        set_owner(user(9));
+        as_caller(user(9));
+        add_campaign(1, 100, 1_000);
+        held_icp_contribution(100, 1, user(1), 100_000);
+        let mut pledge = contribution(101, 1, user(50), 50_000, PaymentMethod::ICP, EscrowStatus::Held);
+        pledge.matched_for = Some(100);
+        insert_contribution(pledge);
+        set_now_secs(1_001);
+        assert_eq!(block_on(preview_release(backend(), 1)).unwrap().fee_e8s, ICP_LEDGER_FEE_E8S);
+        assert_eq!(block_on(release_campaign(backend(), 1)), Ok(2));
+        assert_eq!(outbound_transfers(), vec![(user(200), 100_000 - ICP_LEDGER_FEE_E8S)], "only the escrowed e8s");
+
+        add_campaign(2, 100, 5_000);
+        set_match_pool(2, MatchPool { sponsor: user(50), match_ratio_bps: 10_000, remaining_e8s: 0 }).unwrap();
+        let mut pledge = contribution(102, 2, user(50), 30_000, PaymentMethod::ICP, EscrowStatus::Pending);
+        pledge.matched_for = Some(103);
+        insert_contribution(pledge);
+        assert_eq!(cancel_campaign_pending(2), Ok(1));
+        assert_eq!(get_match_pool(2).unwrap().remaining_e8s, 30_000);
+    }
+
+    #[test]
+    fn imported_icp_is_refused_and_never_leaves_escrow() {
+        set_owner(user(9));
+        as_caller(user(9));
+        add_campaign(1, 10_000_000, 1_000);
+        add_campaign(2, 10_000_000, 5_000);
+        let record = ImportedContribution { campaign_id: 1, backer: user(1), amount: 100_000, method: PaymentMethod::ICP, created_at_ns: 5 };
+        assert_eq!(
+            block_on(import_contributions(backend(), vec![record])).unwrap(),
+            vec![Err("ICP contributions must arrive through the ledger, not an import".to_string())]
+        );
+
+        // a row imported before ICP was refused: no transfer backs it
+        let mut legacy = contribution(100, 1, user(1), 100_000, PaymentMethod::ICP, EscrowStatus::Held);
+        legacy.imported_at_ns = Some(5);
+        insert_contribution(legacy);
+        set_now_secs(1_001);
+        as_caller(user(1));
+        assert_eq!(
+            block_on(roll_over_contribution(backend(), 100, 2)),
+            Err("ICP that never reached escrow can't be rolled over".to_string())
+        );
+        as_caller(user(9));
+        block_on(refund_campaign(backend(), 1)).unwrap();
+        assert!(outbound_transfers().is_empty(), "nothing was in escrow to send back");
+    }
+
+    #[test]
+    fn fiat_backs_a_campaign_the_backend_created_without_a_currency() {
+        // `create_campaign` leaves currency and decimals unset
+        let mut meta = add_campaign(1, 1_000, 10_000);
+        as_caller(user(1));
+        register_user("Ann".into(), "ann@example.com".into()).unwrap();
+        assert!(block_on(contribute(backend(), 1, 400, PaymentMethod::BankTransfer)).is_ok());
+
+        (meta.currency, meta.decimals) = (Some("ICP".into()), Some(8));
+        mock::CAMPAIGN_META.with(|m| m.borrow_mut().insert(1, meta));
+        assert_eq!(
+            block_on(contribute(backend(), 1, 400, PaymentMethod::BankTransfer)),
+            Err("fiat payments can't be counted toward an ICP goal".to_string())
+        );
+    }
+
+    #[test]
+    fn debug_dump_shows_the_fee_collector_falling_back_to_the_owner() {
+        set_owner(user(9));
+        as_caller(user(9));
+        assert_eq!(debug_dump().unwrap().fee_collector, user(9).to_text());
+        set_fee_collector(user(30)).unwrap();
+        assert_eq!(debug_dump().unwrap().fee_collector, user(30).to_text());
+    }
+
+    #[test]
+    fn a_roll_over_moves_the_icp_from_the_old_campaigns_total_to_the_new_one() {
+        add_campaign(1, 10_000_000, 1_000);
+        add_campaign(2, 10_000_000, 5_000);
+        held_icp_contribution(100, 1, user(1), 100_000);
+        set_now_secs(1_001);
+
+        as_caller(user(1));
+        let id = block_on(roll_over_contribution(backend(), 100, 2)).unwrap();
+        assert_eq!(get_contribution(id).unwrap().rolled_over_from, Some(100));
+        let sent = mock::NOTIFICATIONS.with(|v| v.borrow().clone());
+        assert!(matches!(
+            sent.as_slice(),
+            [
+                BackendNotification::IcpRefund { campaign_id: 1, amount_e8s: 100_000 },
+                BackendNotification::IcpContribution { campaign_id: 2, amount_e8s: 100_000, .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn held_icp_counts_only_escrowed_rows_so_an_imported_one_is_not_paid_out() {
+        let meta = add_campaign(1, 100, 1_000);
+        held_icp_contribution(100, 1, user(1), 100_000);
+        let mut imported = contribution(101, 1, user(2), 40_000, PaymentMethod::ICP, EscrowStatus::Held);
+        imported.imported_at_ns = Some(5);
+        insert_contribution(imported);
+
+        let held = collect_held(1, &meta).unwrap();
+        assert_eq!((held.icp, held.total, held.ids.len()), (100_000, 140_000, 2));
+        set_now_secs(1_001);
+        assert_eq!(block_on(release_campaign(backend(), 1)), Ok(2));
+        assert_eq!(outbound_transfers(), vec![(user(200), 100_000 - ICP_LEDGER_FEE_E8S)]);
+    }
+}